@@ -0,0 +1,141 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use aws_sdk_s3::Client;
+use tokio::{sync::mpsc, task::JoinHandle, time::sleep};
+
+use super::{JobParams, ProgressReport, progress::Progress};
+use crate::{
+    buffering::{BufferedObject, ObjectRouter},
+    database::jobs::{self, JobStatus},
+    scanner::ListingCursor,
+    utils::S3Object,
+};
+
+/// The number of times a failed listing is retried before the job fails.
+const MAX_LISTING_RETRIES: u32 = 5;
+
+/// The delay before the first retry of a failed listing, doubled after each retry.
+const INITIAL_LISTING_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// A one-shot job ingesting all objects in a range of a bucket prefix.
+pub struct Job {
+    id: uuid::Uuid,
+    handle: JoinHandle<()>,
+    progress: Arc<Progress>,
+}
+
+impl Job {
+    pub fn spawn(id: uuid::Uuid, client: Client, params: JobParams, router: ObjectRouter) -> Self {
+        let progress = Arc::new(Progress::default());
+        let handle = tokio::spawn({
+            let progress = progress.clone();
+            async move {
+                if let Err(e) = execute(id, client, params, router, progress.clone()).await {
+                    log::error!("[{id}] Backfill job execution failed: {e:?}");
+                    progress.set_failed();
+                    if let Err(e) = jobs::update_status(id, JobStatus::Failed).await {
+                        log::error!("[{id}] Failed to mark the backfill job as failed: {e:?}");
+                    }
+                }
+            }
+        });
+        Self {
+            id,
+            handle,
+            progress,
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.handle.abort();
+    }
+
+    pub const fn get_id(&self) -> uuid::Uuid {
+        self.id
+    }
+
+    pub fn get_progress(&self) -> ProgressReport {
+        self.progress.report()
+    }
+}
+
+async fn execute(
+    id: uuid::Uuid,
+    client: Client,
+    params: JobParams,
+    router: ObjectRouter,
+    progress: Arc<Progress>,
+) -> Result<()> {
+    let (notifier, mut handled_objects) = mpsc::unbounded_channel();
+    let key_filter = params.get_object_params().get_key_filter()?;
+    let object_limits = params.get_object_params().get_object_limits();
+    let mut cursor = ListingCursor::new(params.get_start_after().map(ToString::to_string));
+    loop {
+        let listed_objects = next_page(id, &client, &params, &mut cursor).await?;
+        let is_past_end = listed_objects
+            .last()
+            .is_some_and(|object| params.is_past_end(object.get_key()));
+        let in_range_objects = listed_objects
+            .into_iter()
+            .filter(|object| !params.is_past_end(object.get_key()))
+            .filter(|object| params.is_modified_in_range(object.get_last_modified()))
+            .collect();
+        for object in object_limits.retain(id, key_filter.retain(id, in_range_objects)) {
+            progress.record_scanned(&object);
+            router
+                .send(BufferedObject::new(object, Some(notifier.clone()), None))
+                .await?;
+        }
+        while let Ok(handled) = handled_objects.try_recv() {
+            progress.record_handled(&handled.object, handled.outcome);
+        }
+        if is_past_end || !cursor.is_mid_pass() {
+            break;
+        }
+    }
+    progress.set_listing_done();
+    log::info!("[{id}] Backfill listing done: {:?}", progress.report());
+
+    // The channel closes once every buffered object has been handled (or dropped).
+    drop(notifier);
+    while let Some(handled) = handled_objects.recv().await {
+        progress.record_handled(&handled.object, handled.outcome);
+    }
+    jobs::update_status(id, JobStatus::Completed).await?;
+    progress.set_completed();
+    log::info!("[{id}] Backfill completed: {:?}", progress.report());
+    Ok(())
+}
+
+/// Lists the next page of the job's range, retrying failed listings with exponential backoff.
+///
+/// A failed listing resets the cursor's continuation token, so that a retry restarts from the last
+/// listed key.
+async fn next_page(
+    id: uuid::Uuid,
+    client: &Client,
+    params: &JobParams,
+    cursor: &mut ListingCursor,
+) -> Result<Vec<S3Object>> {
+    let mut delay = INITIAL_LISTING_RETRY_DELAY;
+    let mut num_retries = 0;
+    loop {
+        match cursor
+            .next_page(client, params.get_bucket(), params.get_key_prefix())
+            .await
+        {
+            Ok(listed_objects) => return Ok(listed_objects),
+            Err(e) if num_retries < MAX_LISTING_RETRIES => {
+                log::warn!(
+                    "[{id}] Failed to list objects, retrying in {}s: {e:?}",
+                    delay.as_secs()
+                );
+                sleep(delay).await;
+                delay *= 2;
+                num_retries += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
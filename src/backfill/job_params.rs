@@ -0,0 +1,159 @@
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{ObjectParams, deserialize_optional_param};
+
+/// Parameters for a backfill job, specifying the S3 region, bucket, and key prefix, and the range
+/// of objects to ingest.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct JobParams {
+    region: String,
+    bucket: String,
+    key_prefix: String,
+    dataset: Option<String>,
+    #[serde(flatten)]
+    object_params: ObjectParams,
+    /// Only keys after this one are ingested.
+    start_after: Option<String>,
+    /// Only keys up to this one (inclusive) are ingested.
+    end_key: Option<String>,
+    /// Only objects last modified at or after this time, in seconds since the Unix epoch, are
+    /// ingested.
+    #[serde(default, deserialize_with = "deserialize_optional_param")]
+    modified_after: Option<i64>,
+    /// Only objects last modified before this time, in seconds since the Unix epoch, are ingested.
+    #[serde(default, deserialize_with = "deserialize_optional_param")]
+    modified_before: Option<i64>,
+}
+
+impl JobParams {
+    pub fn get_region(&self) -> &str {
+        &self.region
+    }
+
+    pub fn get_bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    pub fn get_key_prefix(&self) -> &str {
+        &self.key_prefix
+    }
+
+    pub fn get_dataset(&self) -> Option<&str> {
+        self.dataset.as_deref()
+    }
+
+    pub const fn get_object_params(&self) -> &ObjectParams {
+        &self.object_params
+    }
+
+    pub fn get_start_after(&self) -> Option<&str> {
+        self.start_after.as_deref()
+    }
+
+    /// Returns whether the given key is past the end of the range.
+    pub fn is_past_end(&self, key: &str) -> bool {
+        self.end_key.as_deref().is_some_and(|end_key| key > end_key)
+    }
+
+    /// Returns whether the given last modification time is within the range. Objects with an
+    /// unknown modification time are always in range.
+    pub fn is_modified_in_range(&self, last_modified: Option<i64>) -> bool {
+        let Some(last_modified) = last_modified else {
+            return true;
+        };
+        self.modified_after
+            .is_none_or(|after| last_modified >= after)
+            && self
+                .modified_before
+                .is_none_or(|before| last_modified < before)
+    }
+
+    /// Validates that the parameters are consistent with each other.
+    pub fn validate(&self) -> Result<()> {
+        self.object_params.validate()?;
+        if let (Some(start_after), Some(end_key)) = (&self.start_after, &self.end_key)
+            && start_after >= end_key
+        {
+            bail!("`start_after` must precede `end_key`.");
+        }
+        if let (Some(after), Some(before)) = (self.modified_after, self.modified_before)
+            && after >= before
+        {
+            bail!("`modified_after` must precede `modified_before`.");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::web::Query;
+
+    use super::*;
+    use crate::utils::OversizedObjectPolicy;
+
+    #[test]
+    fn test_range() {
+        let params: JobParams = serde_json::from_str(
+            r#"{
+                "region": "us-east-1",
+                "bucket": "bucket",
+                "key_prefix": "logs/",
+                "end_key": "logs/b",
+                "modified_after": 100,
+                "modified_before": 200
+            }"#,
+        )
+        .unwrap();
+        assert!(params.validate().is_ok());
+        assert!(!params.is_past_end("logs/a"));
+        assert!(!params.is_past_end("logs/b"));
+        assert!(params.is_past_end("logs/b0"));
+        assert!(params.is_modified_in_range(None));
+        assert!(params.is_modified_in_range(Some(100)));
+        assert!(!params.is_modified_in_range(Some(99)));
+        assert!(!params.is_modified_in_range(Some(200)));
+    }
+
+    #[test]
+    fn test_query_params() {
+        let params = Query::<JobParams>::from_query(
+            "region=us-east-1&bucket=bucket&key_prefix=logs%2F&exclude_glob=**%2F*.tmp&\
+             max_object_size=100&oversized_object_policy=isolate&modified_after=100",
+        )
+        .unwrap()
+        .into_inner();
+        assert!(params.validate().is_ok());
+        assert!(!params.is_modified_in_range(Some(99)));
+        let object_params = params.get_object_params();
+        assert!(
+            !object_params
+                .get_key_filter()
+                .unwrap()
+                .matches("logs/a.tmp")
+        );
+        let object_limits = object_params.get_object_limits();
+        assert_eq!(Some(100), object_limits.max_size);
+        assert_eq!(
+            OversizedObjectPolicy::Isolate,
+            object_limits.oversized_policy
+        );
+
+        // The parameters are stored as JSON, where numbers aren't strings.
+        let stored: JobParams =
+            serde_json::from_str(serde_json::to_string(&params).unwrap().as_str()).unwrap();
+        assert_eq!(
+            Some(100),
+            stored.get_object_params().get_object_limits().max_size
+        );
+        assert!(!stored.is_modified_in_range(Some(99)));
+
+        assert!(
+            Query::<JobParams>::from_query(
+                "region=us-east-1&bucket=bucket&key_prefix=logs%2F&max_object_size=large"
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,7 @@
+mod job;
+mod job_params;
+mod progress;
+
+pub use job::Job;
+pub use job_params::JobParams;
+pub use progress::ProgressReport;
@@ -0,0 +1,114 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use serde::Serialize;
+
+use crate::{buffering::HandlingOutcome, utils::S3Object};
+
+/// The progress of a backfill job, updated as objects are listed and handled.
+#[derive(Default)]
+pub struct Progress {
+    objects_scanned: AtomicU64,
+    bytes_scanned: AtomicU64,
+    objects_submitted: AtomicU64,
+    bytes_submitted: AtomicU64,
+    objects_skipped: AtomicU64,
+    listing_done: AtomicBool,
+    completed: AtomicBool,
+    failed: AtomicBool,
+}
+
+/// A snapshot of the progress of a backfill job.
+#[derive(Debug, Serialize)]
+pub struct ProgressReport {
+    pub state: &'static str,
+    pub objects_scanned: u64,
+    pub bytes_scanned: u64,
+    /// Objects submitted in a compression job, or spilled to the local spill queue.
+    pub objects_submitted: u64,
+    pub bytes_submitted: u64,
+    /// Objects that had already been ingested.
+    pub objects_skipped: u64,
+}
+
+impl Progress {
+    pub fn record_scanned(&self, object: &S3Object) {
+        self.objects_scanned.fetch_add(1, Ordering::Relaxed);
+        self.bytes_scanned
+            .fetch_add(object.get_size() as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_handled(&self, object: &S3Object, outcome: HandlingOutcome) {
+        match outcome {
+            HandlingOutcome::Submitted { .. } | HandlingOutcome::Spilled => {
+                self.objects_submitted.fetch_add(1, Ordering::Relaxed);
+                self.bytes_submitted
+                    .fetch_add(object.get_size() as u64, Ordering::Relaxed);
+            }
+            HandlingOutcome::Skipped | HandlingOutcome::Duplicate | HandlingOutcome::Removed => {
+                self.objects_skipped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn set_listing_done(&self) {
+        self.listing_done.store(true, Ordering::Relaxed);
+    }
+
+    pub fn set_completed(&self) {
+        self.completed.store(true, Ordering::Relaxed);
+    }
+
+    pub fn set_failed(&self) {
+        self.failed.store(true, Ordering::Relaxed);
+    }
+
+    pub fn report(&self) -> ProgressReport {
+        let state = if self.failed.load(Ordering::Relaxed) {
+            "failed"
+        } else if self.completed.load(Ordering::Relaxed) {
+            "completed"
+        } else if self.listing_done.load(Ordering::Relaxed) {
+            "submitting"
+        } else {
+            "listing"
+        };
+        ProgressReport {
+            state,
+            objects_scanned: self.objects_scanned.load(Ordering::Relaxed),
+            bytes_scanned: self.bytes_scanned.load(Ordering::Relaxed),
+            objects_submitted: self.objects_submitted.load(Ordering::Relaxed),
+            bytes_submitted: self.bytes_submitted.load(Ordering::Relaxed),
+            objects_skipped: self.objects_skipped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_state() {
+        let object = S3Object::new("bucket".to_owned(), "logs/a.log".to_owned(), 10, None);
+        let progress = Progress::default();
+        assert_eq!("listing", progress.report().state);
+        progress.record_scanned(&object);
+        progress.set_listing_done();
+        assert_eq!("submitting", progress.report().state);
+        progress.record_handled(&object, HandlingOutcome::Spilled);
+        progress.set_completed();
+        let report = progress.report();
+        assert_eq!("completed", report.state);
+        assert_eq!((1, 10), (report.objects_scanned, report.bytes_scanned));
+        assert_eq!((1, 10), (report.objects_submitted, report.bytes_submitted));
+
+        // A failure is reported whatever stage the job reached.
+        let progress = Progress::default();
+        progress.set_failed();
+        assert_eq!("failed", progress.report().state);
+        let progress = Progress::default();
+        progress.set_listing_done();
+        progress.set_failed();
+        assert_eq!("failed", progress.report().state);
+    }
+}
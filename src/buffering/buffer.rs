@@ -1,41 +1,126 @@
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+
 use anyhow::Result;
+use tokio::time::Instant;
 
-use super::ListenerKey;
+use super::{BufferedObject, HandlingOutcome, ListenerKey, ObjectRemoval, SpillQueue};
 use crate::{
     compression::{
         config::{AwsAuthentication, AwsCredentials, Input, JobConfig, Output},
-        submit_compression_job,
+        submit_and_track,
     },
+    database::{ledger, ledger::ObjectStatus},
+    metrics,
     utils::S3Object,
 };
 
+/// Configuration for retrying compression job submissions that failed.
+#[derive(Clone)]
+pub struct SubmissionRetryConfig {
+    /// The number of submission attempts after which a batch is spilled to `spill_queue`.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub spill_queue: Arc<SpillQueue>,
+}
+
 pub struct Buffer {
     tag: String,
-    buffered_objects: Vec<S3Object>,
+    buffered_objects: Vec<BufferedObject>,
     listener_key: ListenerKey,
     total_buffered_size: usize,
     size_threshold: usize,
+    retry_config: SubmissionRetryConfig,
+    failed_batches: VecDeque<FailedBatch>,
+}
+
+/// A batch of objects whose compression job submission failed, waiting to be retried.
+struct FailedBatch {
+    objects: Vec<BufferedObject>,
+    num_attempts: u32,
+    next_attempt: Instant,
 }
 
 impl Buffer {
-    pub fn new(listener_key: ListenerKey, size_threshold: usize) -> Self {
-        let buffer_tag = format!(
-            "{}-{}-{}",
-            listener_key.get_dataset().unwrap_or("default"),
-            listener_key.get_bucket(),
-            listener_key.get_access_key_id()
-        );
+    pub fn new(
+        listener_key: ListenerKey,
+        size_threshold: usize,
+        retry_config: SubmissionRetryConfig,
+    ) -> Self {
         Self {
-            tag: buffer_tag,
+            tag: listener_key.get_tag(),
             buffered_objects: Vec::new(),
             listener_key,
             total_buffered_size: 0,
             size_threshold,
+            retry_config,
+            failed_batches: VecDeque::new(),
         }
     }
 
-    pub async fn add_object(&mut self, object: S3Object) -> Result<()> {
-        self.total_buffered_size += object.get_size();
+    /// Claims the given objects in the ingestion ledger, and buffers the ones that haven't been
+    /// claimed or ingested yet.
+    pub async fn add_objects(&mut self, objects: Vec<BufferedObject>) -> Result<()> {
+        let s3_objects: Vec<&S3Object> = objects.iter().map(BufferedObject::get_object).collect();
+        let claims = ledger::claim(&s3_objects).await.unwrap_or_else(|e| {
+            // Prefer duplicates over losing the objects.
+            log::error!(
+                "[{}] Failed to claim {} objects in the ingestion ledger. Buffering them anyway. \
+                 Error: {}",
+                self.tag.as_str(),
+                objects.len(),
+                e
+            );
+            vec![None; objects.len()]
+        });
+        for (object, claim) in objects.into_iter().zip(claims) {
+            self.add_object(object, claim).await?;
+        }
+        Ok(())
+    }
+
+    async fn add_object(
+        &mut self,
+        object: BufferedObject,
+        claim: Option<ObjectStatus>,
+    ) -> Result<()> {
+        match claim {
+            None => {}
+            Some(ObjectStatus::Buffered) => {
+                // Another buffer holds the object, and the producer that sent it there holds it
+                // back until it's durably handled.
+                log::info!(
+                    "[{}] Object {:?} is being ingested by another buffer. Skipping.",
+                    self.tag.as_str(),
+                    object.get_object()
+                );
+                object.notify(HandlingOutcome::Duplicate);
+                return Ok(());
+            }
+            Some(_) => {
+                log::info!(
+                    "[{}] Object {:?} has already been ingested. Skipping.",
+                    self.tag.as_str(),
+                    object.get_object()
+                );
+                object.notify(HandlingOutcome::Skipped);
+                return Ok(());
+            }
+        }
+
+        if object.is_isolated() {
+            // Flush the buffered objects first, so that batches are still submitted in order.
+            self.flush().await?;
+            log::info!(
+                "[{}] Submitting object {:?} in its own compression job.",
+                self.tag.as_str(),
+                object.get_object()
+            );
+            self.submit_in_order(vec![object]).await;
+            return Ok(());
+        }
+
+        self.total_buffered_size += object.get_object().get_size();
         self.buffered_objects.push(object);
         if self.total_buffered_size < self.size_threshold {
             return Ok(());
@@ -44,6 +129,33 @@ impl Buffer {
         Ok(())
     }
 
+    /// Drops the not-yet-flushed objects matching the given removal from the buffer, releasing
+    /// their claims in the ingestion ledger.
+    pub async fn remove_object(&mut self, removal: &ObjectRemoval) {
+        let (removed, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.buffered_objects)
+            .into_iter()
+            .partition(|object| removal.matches(object.get_object()));
+        self.buffered_objects = kept;
+
+        for object in removed {
+            self.total_buffered_size -= object.get_object().get_size();
+            if let Err(e) = ledger::release(object.get_object()).await {
+                log::error!(
+                    "[{}] Failed to release removed object {:?} in the ingestion ledger. Error: {}",
+                    self.tag.as_str(),
+                    object.get_object(),
+                    e
+                );
+            }
+            log::info!(
+                "[{}] Dropped removed object {:?} from the buffer.",
+                self.tag.as_str(),
+                object.get_object()
+            );
+            object.notify(HandlingOutcome::Removed);
+        }
+    }
+
     pub async fn flush(&mut self) -> Result<()> {
         if self.buffered_objects.is_empty() {
             log::info!("[{}] Buffer is empty, nothing to flush.", self.tag.as_str());
@@ -56,67 +168,175 @@ impl Buffer {
             self.total_buffered_size
         );
 
-        let mut keys = Vec::new();
-        for obj in &self.buffered_objects {
-            log::info!("Submitting object with key: {:?}", obj.get_key());
-            keys.push(obj.get_key().to_owned());
+        let objects = std::mem::take(&mut self.buffered_objects);
+        self.clear();
+        self.submit_in_order(objects).await;
+        Ok(())
+    }
+
+    /// Submits a new batch of objects, or queues it behind the failed batches if there are any, so
+    /// that batches are submitted in flush order.
+    async fn submit_in_order(&mut self, objects: Vec<BufferedObject>) {
+        if !self.failed_batches.is_empty() {
+            self.failed_batches.push_back(FailedBatch {
+                objects,
+                num_attempts: 0,
+                next_attempt: Instant::now(),
+            });
+            return;
         }
+        if let Some(failed_batch) = self.submit_batch(objects, 0).await {
+            self.failed_batches.push_back(failed_batch);
+        }
+    }
 
-        let job_config = JobConfig {
-            input: Input {
-                aws_authentication: AwsAuthentication::Credentials {
-                    credentials: AwsCredentials {
-                        access_key_id: self.listener_key.get_access_key_id().to_string(),
-                        secret_access_key: self.listener_key.get_secret_access_key().to_string(),
-                    },
-                },
-                bucket: self.listener_key.get_bucket().to_string(),
-                dataset: self
-                    .listener_key
-                    .get_dataset()
-                    .unwrap_or("default")
-                    .to_string(),
-                key_prefix: self.listener_key.get_key_prefix().to_string(),
-                region_code: self.listener_key.get_region().to_string(),
-                keys: Some(keys),
-            },
-            output: Output {
-                compression_level: 3,
-                target_archive_size: 268_435_456,
-                target_dictionaries_size: 33_554_432,
-                target_encoded_file_size: 268_435_456,
-                target_segment_size: 268_435_456,
-            },
-        };
+    /// Returns the time at which the oldest failed batch is due for a retry, if any.
+    pub fn get_next_retry_time(&self) -> Option<Instant> {
+        self.failed_batches.front().map(|batch| batch.next_attempt)
+    }
 
-        match submit_compression_job(job_config).await {
-            Ok(compression_job_id) => {
-                log::info!(
-                    "[{}] Submitted compression job. Job ID: {}.",
+    /// Resubmits failed batches in flush order, as long as their backoff has elapsed.
+    ///
+    /// A batch that fails again holds back all later batches until its next retry.
+    pub async fn retry_failed_batches(&mut self) {
+        while self
+            .failed_batches
+            .front()
+            .is_some_and(|batch| batch.next_attempt <= Instant::now())
+        {
+            let Some(batch) = self.failed_batches.pop_front() else {
+                break;
+            };
+            log::info!(
+                "[{}] Retrying submission of {} objects (attempt {}).",
+                self.tag.as_str(),
+                batch.objects.len(),
+                batch.num_attempts + 1
+            );
+            metrics::increment(
+                "compression_submission_retries_total",
+                &[("buffer", self.tag.as_str())],
+            );
+            if let Some(failed_batch) = self.submit_batch(batch.objects, batch.num_attempts).await {
+                self.failed_batches.push_front(failed_batch);
+                break;
+            }
+        }
+    }
+
+    /// Spills all failed batches to the spill queue without further retries.
+    pub async fn spill_failed_batches(&mut self) {
+        while let Some(batch) = self.failed_batches.pop_front() {
+            if let Err(batch) = self.spill(batch.objects).await {
+                log::error!(
+                    "[{}] Dropping {} objects that could neither be submitted nor spilled.",
                     self.tag.as_str(),
-                    compression_job_id
+                    batch.len()
                 );
             }
+        }
+    }
+
+    /// Submits a batch of objects in a compression job, spilling it once it has failed too many
+    /// times.
+    ///
+    /// # Returns
+    ///
+    /// The batch back if it failed and must be retried.
+    async fn submit_batch(
+        &self,
+        objects: Vec<BufferedObject>,
+        num_previous_attempts: u32,
+    ) -> Option<FailedBatch> {
+        let s3_objects: Vec<&S3Object> = objects.iter().map(BufferedObject::get_object).collect();
+        for object in &s3_objects {
+            log::info!("Submitting object with key: {:?}", object.get_key());
+        }
+        let job_config = build_job_config(&self.listener_key, &s3_objects);
+        let e = match submit_and_track(self.tag.as_str(), job_config, &s3_objects).await {
+            Ok(compression_job_id) => {
+                for object in objects {
+                    object.notify(HandlingOutcome::Submitted { compression_job_id });
+                }
+                return None;
+            }
+            Err(e) => e,
+        };
+
+        let num_attempts = num_previous_attempts + 1;
+        log::error!(
+            "[{}] Failed to submit compression job for {} objects (attempt {}/{}). Error: {}",
+            self.tag.as_str(),
+            objects.len(),
+            num_attempts,
+            self.retry_config.max_attempts,
+            e
+        );
+        if num_attempts >= self.retry_config.max_attempts {
+            metrics::increment(
+                "compression_submission_failures_total",
+                &[("buffer", self.tag.as_str())],
+            );
+            // Keep retrying at the slowest pace rather than losing the objects.
+            return self.spill(objects).await.err().map(|objects| FailedBatch {
+                objects,
+                num_attempts,
+                next_attempt: Instant::now() + self.retry_config.max_backoff,
+            });
+        }
+
+        let backoff = self
+            .retry_config
+            .initial_backoff
+            .saturating_mul(1 << (num_attempts - 1).min(16))
+            .min(self.retry_config.max_backoff);
+        Some(FailedBatch {
+            objects,
+            num_attempts,
+            next_attempt: Instant::now() + backoff,
+        })
+    }
+
+    /// Spills the given objects to the spill queue.
+    ///
+    /// # Returns
+    ///
+    /// The objects back if they couldn't be spilled.
+    async fn spill(
+        &self,
+        objects: Vec<BufferedObject>,
+    ) -> std::result::Result<(), Vec<BufferedObject>> {
+        let s3_objects = objects
+            .iter()
+            .map(|object| object.get_object().clone())
+            .collect();
+        let mut job_ids: Vec<_> = objects
+            .iter()
+            .filter_map(BufferedObject::get_job_id)
+            .collect();
+        job_ids.sort_unstable();
+        job_ids.dedup();
+        match self
+            .retry_config
+            .spill_queue
+            .push(self.tag.as_str(), &self.listener_key, job_ids, s3_objects)
+            .await
+        {
+            Ok(()) => {
+                for object in objects {
+                    object.notify(HandlingOutcome::Spilled);
+                }
+                Ok(())
+            }
             Err(e) => {
                 log::error!(
-                    "[{}] Failed to submit compression job for object. Error: {}",
+                    "[{}] Failed to spill objects. Error: {}",
                     self.tag.as_str(),
                     e
                 );
+                Err(objects)
             }
         }
-
-        log::info!(
-            "[{}] Flushing objects:\n{}",
-            self.tag.as_str(),
-            self.buffered_objects
-                .iter()
-                .map(|obj| format!("{obj:?}"))
-                .collect::<Vec<_>>()
-                .join("\n")
-        );
-        self.clear();
-        Ok(())
     }
 
     fn clear(&mut self) {
@@ -124,3 +344,33 @@ impl Buffer {
         self.total_buffered_size = 0;
     }
 }
+
+/// Builds the config of a compression job ingesting the given objects of a listener.
+pub(super) fn build_job_config(listener_key: &ListenerKey, objects: &[&S3Object]) -> JobConfig {
+    let keys = objects
+        .iter()
+        .map(|object| object.get_key().to_owned())
+        .collect();
+    JobConfig {
+        input: Input {
+            aws_authentication: AwsAuthentication::Credentials {
+                credentials: AwsCredentials {
+                    access_key_id: listener_key.get_access_key_id().to_string(),
+                    secret_access_key: listener_key.get_secret_access_key().to_string(),
+                },
+            },
+            bucket: listener_key.get_bucket().to_string(),
+            dataset: listener_key.get_dataset().unwrap_or("default").to_string(),
+            key_prefix: listener_key.get_key_prefix().to_string(),
+            region_code: listener_key.get_region().to_string(),
+            keys: Some(keys),
+        },
+        output: Output {
+            compression_level: 3,
+            target_archive_size: 268_435_456,
+            target_dictionaries_size: 33_554_432,
+            target_encoded_file_size: 268_435_456,
+            target_segment_size: 268_435_456,
+        },
+    }
+}
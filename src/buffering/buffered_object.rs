@@ -0,0 +1,96 @@
+use tokio::sync::mpsc::UnboundedSender;
+use uuid::Uuid;
+
+use crate::utils::S3Object;
+
+/// An object sent to a [`super::Listener`] for buffering.
+///
+/// The producer may attach a notifier to be told once the object has been durably handled, and the
+/// receipt handle of the SQS message the object came from.
+#[derive(Debug)]
+pub struct BufferedObject {
+    object: S3Object,
+    notifier: Option<UnboundedSender<HandledObject>>,
+    receipt_handle: Option<String>,
+    /// The ID of the job that sent the object, set by the job's [`super::ObjectRouter`].
+    job_id: Option<Uuid>,
+    /// Whether the object is submitted alone in its own compression job.
+    isolated: bool,
+}
+
+/// How a buffered object has been durably handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HandlingOutcome {
+    /// The object has been submitted in the given compression job.
+    Submitted { compression_job_id: u64 },
+    /// The object's submission kept failing and it has been spilled to the local spill queue.
+    Spilled,
+    /// The object had already been ingested and was skipped.
+    Skipped,
+    /// The object is being ingested through another buffer, which is then responsible for it, and
+    /// was skipped.
+    Duplicate,
+    /// The object was removed from S3 before it was flushed, and has been dropped from the buffer.
+    Removed,
+}
+
+/// A notification that a buffered object has been durably handled.
+#[derive(Debug)]
+pub struct HandledObject {
+    pub object: S3Object,
+    pub receipt_handle: Option<String>,
+    pub outcome: HandlingOutcome,
+}
+
+impl BufferedObject {
+    pub const fn new(
+        object: S3Object,
+        notifier: Option<UnboundedSender<HandledObject>>,
+        receipt_handle: Option<String>,
+    ) -> Self {
+        Self {
+            object,
+            notifier,
+            receipt_handle,
+            job_id: None,
+            isolated: false,
+        }
+    }
+
+    #[must_use]
+    pub const fn sent_by(mut self, job_id: Uuid) -> Self {
+        self.job_id = Some(job_id);
+        self
+    }
+
+    pub const fn get_job_id(&self) -> Option<Uuid> {
+        self.job_id
+    }
+
+    #[must_use]
+    pub const fn isolated(mut self) -> Self {
+        self.isolated = true;
+        self
+    }
+
+    pub const fn is_isolated(&self) -> bool {
+        self.isolated
+    }
+
+    pub const fn get_object(&self) -> &S3Object {
+        &self.object
+    }
+
+    /// Notifies the producer, if any, that the object has been durably handled.
+    pub fn notify(self, outcome: HandlingOutcome) {
+        if let Some(notifier) = self.notifier {
+            // The producer may have been cancelled already, in which case nobody is interested in
+            // the notification.
+            let _ = notifier.send(HandledObject {
+                object: self.object,
+                receipt_handle: self.receipt_handle,
+                outcome,
+            });
+        }
+    }
+}
@@ -8,34 +8,78 @@ use tokio::{
     time::{Instant, Sleep, sleep_until},
 };
 
-use super::{Buffer, ListenerKey};
-use crate::utils::S3Object;
+use super::{Buffer, BufferedObject, ListenerKey, ObjectRemoval, SubmissionRetryConfig};
+
+/// The maximum number of received objects claimed together in the ingestion ledger.
+const MAX_CLAIM_BATCH_SIZE: usize = 100;
+
+/// A message sent to a [`Listener`].
+#[derive(Debug)]
+pub enum ListenerMessage {
+    /// An object to buffer.
+    Object(BufferedObject),
+    /// An object that has been removed from S3, which is dropped from the buffer if it hasn't been
+    /// flushed yet.
+    Removal(ObjectRemoval),
+}
+
+impl From<BufferedObject> for ListenerMessage {
+    fn from(object: BufferedObject) -> Self {
+        Self::Object(object)
+    }
+}
 
 pub struct Listener {
-    sender: mpsc::Sender<S3Object>,
+    sender: mpsc::Sender<ListenerMessage>,
     #[allow(dead_code)]
     handle: JoinHandle<()>,
 }
 
 async fn listen(
-    mut receiver: mpsc::Receiver<S3Object>,
+    mut receiver: mpsc::Receiver<ListenerMessage>,
     mut buffer: Buffer,
     timeout: Duration,
 ) -> Result<()> {
     let mut timer: Pin<Box<Sleep>> = Box::pin(sleep_until(Instant::now() + timeout));
 
     loop {
+        let next_retry_time = buffer.get_next_retry_time();
         select! {
-            // Receiving an object
-            maybe_object = receiver.recv() => {
-                if let Some(object) = maybe_object {
-                    buffer.add_object(object).await?;
-                    timer.as_mut().reset(Instant::now() + timeout);
+            // Receiving a message
+            maybe_message = receiver.recv() => {
+                if let Some(message) = maybe_message {
+                    match message {
+                        ListenerMessage::Object(object) => {
+                            // Objects already waiting in the channel are claimed together.
+                            let mut objects = vec![object];
+                            let mut removal = None;
+                            while objects.len() < MAX_CLAIM_BATCH_SIZE {
+                                match receiver.try_recv() {
+                                    Ok(ListenerMessage::Object(object)) => objects.push(object),
+                                    Ok(ListenerMessage::Removal(next_removal)) => {
+                                        removal = Some(next_removal);
+                                        break;
+                                    }
+                                    Err(_) => break,
+                                }
+                            }
+                            buffer.add_objects(objects).await?;
+                            if let Some(removal) = removal {
+                                buffer.remove_object(&removal).await;
+                            }
+                            timer.as_mut().reset(Instant::now() + timeout);
+                        }
+                        ListenerMessage::Removal(removal) => {
+                            buffer.remove_object(&removal).await;
+                        }
+                    }
                 } else {
                     log::error!(
                         "Receiver channel closed unexpectedly."
                     );
-                    return buffer.flush().await;
+                    buffer.flush().await?;
+                    buffer.spill_failed_batches().await;
+                    return Ok(());
                 }
             },
 
@@ -45,6 +89,12 @@ async fn listen(
                 buffer.flush().await?;
                 timer.as_mut().reset(Instant::now() + timeout);
             }
+
+            // Failed batches due for a retry
+            () = sleep_until(next_retry_time.unwrap_or_else(Instant::now)),
+                if next_retry_time.is_some() => {
+                buffer.retry_failed_batches().await;
+            }
         }
     }
 }
@@ -55,13 +105,18 @@ impl Listener {
         timeout: Duration,
         channel_size: usize,
         buffer_size: usize,
+        retry_config: SubmissionRetryConfig,
     ) -> Self {
         let (sender, receiver) = mpsc::channel(channel_size);
         Self {
             sender,
             handle: tokio::spawn(async move {
-                if let Err(e) =
-                    listen(receiver, Buffer::new(listener_key, buffer_size), timeout).await
+                if let Err(e) = listen(
+                    receiver,
+                    Buffer::new(listener_key, buffer_size, retry_config),
+                    timeout,
+                )
+                .await
                 {
                     log::error!("Listener encountered an error: {e:?}");
                 }
@@ -69,7 +124,7 @@ impl Listener {
         }
     }
 
-    pub fn get_new_sender(&self) -> mpsc::Sender<S3Object> {
+    pub fn get_new_sender(&self) -> mpsc::Sender<ListenerMessage> {
         self.sender.clone()
     }
 }
@@ -27,6 +27,25 @@ impl ListenerKey {
         }
     }
 
+    /// Returns the key of the listener ingesting the same objects into another dataset.
+    #[must_use]
+    pub fn with_dataset(&self, dataset: Option<String>) -> Self {
+        Self {
+            dataset,
+            ..self.clone()
+        }
+    }
+
+    /// Returns the tag identifying the listener's buffer in logs and metric labels, which leaves
+    /// out the credentials.
+    pub fn get_tag(&self) -> String {
+        format!(
+            "{}-{}",
+            self.get_dataset().unwrap_or("default"),
+            self.get_bucket()
+        )
+    }
+
     pub fn get_bucket(&self) -> &str {
         &self.bucket
     }
@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use dashmap::DashMap;
+use tokio::sync::mpsc::Sender;
+
+use super::{Listener, ListenerKey, ListenerMessage, SubmissionRetryConfig};
+
+/// The listeners shared by all jobs, spawned on demand for each [`ListenerKey`].
+pub struct ListenerRegistry {
+    listener_table: DashMap<ListenerKey, Listener>,
+    listener_channel_size: usize,
+    listener_channel_timeout: Duration,
+    buffer_size: usize,
+    retry_config: SubmissionRetryConfig,
+}
+
+impl ListenerRegistry {
+    pub fn new(
+        listener_channel_size: usize,
+        listener_channel_timeout: Duration,
+        buffer_size: usize,
+        retry_config: SubmissionRetryConfig,
+    ) -> Self {
+        Self {
+            listener_table: DashMap::new(),
+            listener_channel_size,
+            listener_channel_timeout,
+            buffer_size,
+            retry_config,
+        }
+    }
+
+    /// Returns a sender to the listener with the given key, spawning the listener if needed.
+    pub fn get_sender(&self, listener_key: ListenerKey) -> Sender<ListenerMessage> {
+        if let Some(listener) = self.listener_table.get(&listener_key) {
+            return listener.get_new_sender();
+        }
+        self.listener_table
+            .entry(listener_key.clone())
+            .or_insert_with(|| {
+                log::info!("Creating a new listener.");
+                Listener::spawn(
+                    listener_key,
+                    self.listener_channel_timeout,
+                    self.listener_channel_size,
+                    self.buffer_size,
+                    self.retry_config.clone(),
+                )
+            })
+            .get_new_sender()
+    }
+}
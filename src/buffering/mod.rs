@@ -1,7 +1,17 @@
 mod buffer;
+mod buffered_object;
 mod listener;
 mod listener_key;
+mod listener_registry;
+mod object_removal;
+mod object_router;
+mod spill_queue;
 
-pub use buffer::Buffer;
-pub use listener::Listener;
+pub use buffer::{Buffer, SubmissionRetryConfig};
+pub use buffered_object::{BufferedObject, HandledObject, HandlingOutcome};
+pub use listener::{Listener, ListenerMessage};
 pub use listener_key::ListenerKey;
+pub use listener_registry::ListenerRegistry;
+pub use object_removal::ObjectRemoval;
+pub use object_router::ObjectRouter;
+pub use spill_queue::SpillQueue;
@@ -0,0 +1,89 @@
+use std::cmp::Ordering;
+
+use crate::utils::S3Object;
+
+/// An object that has been removed from S3, as reported by an `ObjectRemoved:*` event.
+#[derive(Debug, Clone)]
+pub struct ObjectRemoval {
+    pub bucket: String,
+    pub key: String,
+    pub etag: Option<String>,
+    /// The sequencer of the removal event.
+    pub sequencer: Option<String>,
+}
+
+impl ObjectRemoval {
+    /// Returns whether the given object is the one that was removed, rather than another object
+    /// uploaded with the same key after the removal.
+    ///
+    /// Objects that can't be told apart from a later upload are never matched, since dropping a
+    /// removed object is only an optimization while dropping a later upload loses it.
+    pub fn matches(&self, object: &S3Object) -> bool {
+        if object.get_bucket() != self.bucket || object.get_key() != self.key {
+            return false;
+        }
+        if let (Some(removal), Some(creation)) = (self.sequencer.as_deref(), object.get_sequencer())
+        {
+            return compare_sequencers(creation, removal) == Ordering::Less;
+        }
+        match (self.etag.as_deref(), object.get_etag()) {
+            (Some(removed_etag), Some(etag)) => removed_etag.trim_matches('"') == etag,
+            _ => false,
+        }
+    }
+}
+
+/// Compares the sequencers of two S3 events of the same key.
+///
+/// Sequencers are hexadecimal strings that may differ in length, so the shorter one is compared as
+/// if padded with leading zeros.
+fn compare_sequencers(lhs: &str, rhs: &str) -> Ordering {
+    let width = lhs.len().max(rhs.len());
+    format!("{lhs:0>width$}")
+        .to_ascii_uppercase()
+        .cmp(&format!("{rhs:0>width$}").to_ascii_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn removal(etag: Option<&str>, sequencer: Option<&str>) -> ObjectRemoval {
+        ObjectRemoval {
+            bucket: "bucket".to_owned(),
+            key: "logs/a.log".to_owned(),
+            etag: etag.map(ToString::to_string),
+            sequencer: sequencer.map(ToString::to_string),
+        }
+    }
+
+    fn object(etag: Option<&str>, sequencer: Option<&str>) -> S3Object {
+        S3Object::new(
+            "bucket".to_owned(),
+            "logs/a.log".to_owned(),
+            1,
+            etag.map(ToString::to_string),
+        )
+        .with_sequencer(sequencer.map(ToString::to_string))
+    }
+
+    #[test]
+    fn test_matches() {
+        // An upload before the removal is dropped, while a later upload of the same key is kept.
+        let removal_event = removal(None, Some("0055AED6DCD90281E5"));
+        assert!(removal_event.matches(&object(Some("a"), Some("0055AED6DCD90281E4"))));
+        assert!(removal_event.matches(&object(Some("a"), Some("55AED6DCD90281E4"))));
+        assert!(!removal_event.matches(&object(Some("a"), Some("0055AED6DCD90281E6"))));
+        assert!(!removal_event.matches(&object(Some("a"), Some("10055AED6DCD90281E5"))));
+
+        // Without sequencers, the ETags must match.
+        assert!(removal(Some("\"a\""), None).matches(&object(Some("a"), None)));
+        assert!(!removal(Some("a"), None).matches(&object(Some("b"), None)));
+        assert!(!removal(None, Some("0055AED6DCD90281E5")).matches(&object(Some("a"), None)));
+        assert!(!removal(None, None).matches(&object(Some("a"), None)));
+
+        let other_key = S3Object::new("bucket".to_owned(), "logs/b.log".to_owned(), 1, None)
+            .with_sequencer(Some("00".to_owned()));
+        assert!(!removal(None, Some("01")).matches(&other_key));
+    }
+}
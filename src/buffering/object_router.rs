@@ -0,0 +1,141 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::mpsc::Sender;
+use uuid::Uuid;
+
+use super::{BufferedObject, ListenerKey, ListenerMessage, ListenerRegistry, ObjectRemoval};
+use crate::utils::DatasetTemplate;
+
+/// Sends a job's objects to the listener of their dataset, handing oversized objects on as
+/// configured.
+#[derive(Clone)]
+pub struct ObjectRouter {
+    job_id: Uuid,
+    sender: Sender<ListenerMessage>,
+    dataset_routing: Option<DatasetRouting>,
+    max_object_size: Option<usize>,
+    oversized_route: OversizedRoute,
+}
+
+/// Routing of objects to per-dataset listeners according to a dataset template.
+#[derive(Clone)]
+struct DatasetRouting {
+    template: DatasetTemplate,
+    listener_key: ListenerKey,
+    listeners: Arc<ListenerRegistry>,
+}
+
+#[derive(Clone)]
+enum OversizedRoute {
+    /// Send oversized objects along with the others.
+    Default,
+    /// Send oversized objects along with the others, to be submitted alone.
+    Isolate,
+    /// Send oversized objects to the listener of another dataset.
+    Listener(Sender<ListenerMessage>),
+}
+
+impl ObjectRouter {
+    pub const fn new(job_id: Uuid, sender: Sender<ListenerMessage>) -> Self {
+        Self {
+            job_id,
+            sender,
+            dataset_routing: None,
+            max_object_size: None,
+            oversized_route: OversizedRoute::Default,
+        }
+    }
+
+    /// Sends each object to the listener of the dataset rendered from its key, obtained from
+    /// `listeners` with the given key in which the dataset is substituted. Objects whose dataset
+    /// can't be rendered are still sent to the default listener.
+    #[must_use]
+    pub fn route_by_dataset_template(
+        mut self,
+        template: DatasetTemplate,
+        listener_key: ListenerKey,
+        listeners: Arc<ListenerRegistry>,
+    ) -> Self {
+        self.dataset_routing = Some(DatasetRouting {
+            template,
+            listener_key,
+            listeners,
+        });
+        self
+    }
+
+    /// Submits objects larger than `max_object_size` alone in their own compression job.
+    #[must_use]
+    pub fn isolate_oversized(mut self, max_object_size: usize) -> Self {
+        self.max_object_size = Some(max_object_size);
+        self.oversized_route = OversizedRoute::Isolate;
+        self
+    }
+
+    /// Sends objects larger than `max_object_size` to the given listener instead.
+    #[must_use]
+    pub fn route_oversized(
+        mut self,
+        max_object_size: usize,
+        sender: Sender<ListenerMessage>,
+    ) -> Self {
+        self.max_object_size = Some(max_object_size);
+        self.oversized_route = OversizedRoute::Listener(sender);
+        self
+    }
+
+    pub async fn send(&self, object: BufferedObject) -> Result<()> {
+        let object = object.sent_by(self.job_id);
+        let is_oversized = self
+            .max_object_size
+            .is_some_and(|max_object_size| object.get_object().get_size() > max_object_size);
+        match &self.oversized_route {
+            OversizedRoute::Isolate if is_oversized => {
+                let sender = self.get_sender(object.get_object().get_key());
+                sender.send(object.isolated().into()).await?;
+            }
+            OversizedRoute::Listener(sender) if is_oversized => {
+                sender.send(object.into()).await?;
+            }
+            _ => {
+                let sender = self.get_sender(object.get_object().get_key());
+                sender.send(object.into()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Notifies every listener the job may have sent an object to that it has been removed from
+    /// S3.
+    pub async fn send_removal(&self, removal: ObjectRemoval) -> Result<()> {
+        if let OversizedRoute::Listener(sender) = &self.oversized_route {
+            sender
+                .send(ListenerMessage::Removal(removal.clone()))
+                .await?;
+        }
+        self.get_sender(removal.key.as_str())
+            .send(ListenerMessage::Removal(removal))
+            .await?;
+        Ok(())
+    }
+
+    fn get_sender(&self, key: &str) -> Sender<ListenerMessage> {
+        let Some(routing) = &self.dataset_routing else {
+            return self.sender.clone();
+        };
+        routing.template.render(key).map_or_else(
+            || {
+                log::warn!(
+                    "Failed to render the dataset of key {key}. Using the job's dataset instead."
+                );
+                self.sender.clone()
+            },
+            |dataset| {
+                routing
+                    .listeners
+                    .get_sender(routing.listener_key.with_dataset(Some(dataset)))
+            },
+        )
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Result, anyhow};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use tokio::{fs, io::AsyncWriteExt, task::JoinHandle, time::sleep};
+use uuid::Uuid;
+
+use super::{ListenerKey, buffer::build_job_config};
+use crate::{
+    compression::submit_and_track,
+    database::{jobs, ledger},
+    metrics,
+    utils::S3Object,
+};
+
+/// A durable on-disk queue of batches whose compression job submission kept failing.
+///
+/// Each batch is stored as a JSON file named after its spill time, so that batches are replayed in
+/// the order they were spilled. Batches don't hold secrets: the secret access key of a batch is
+/// looked up in the job table when it's replayed, from the jobs that sent its objects.
+pub struct SpillQueue {
+    dir: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SpilledBatch {
+    listener: SpilledListener,
+    /// The IDs of the jobs that sent the batch's objects, which all share the listener's
+    /// credentials.
+    job_ids: Vec<String>,
+    objects: Vec<S3Object>,
+}
+
+/// The [`ListenerKey`] of a spilled batch, without the secret access key.
+#[derive(Serialize, Deserialize)]
+struct SpilledListener {
+    dataset: Option<String>,
+    bucket: String,
+    key_prefix: String,
+    region: String,
+    access_key_id: String,
+}
+
+impl SpillQueue {
+    pub const fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Persists a batch to the queue.
+    pub async fn push(
+        &self,
+        tag: &str,
+        listener_key: &ListenerKey,
+        job_ids: Vec<Uuid>,
+        objects: Vec<S3Object>,
+    ) -> Result<()> {
+        fs::create_dir_all(&self.dir).await?;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+        let path = self
+            .dir
+            .join(format!("{timestamp:020}-{}.json", uuid::Uuid::new_v4()));
+
+        let batch = SpilledBatch {
+            listener: SpilledListener {
+                dataset: listener_key.get_dataset().map(ToString::to_string),
+                bucket: listener_key.get_bucket().to_string(),
+                key_prefix: listener_key.get_key_prefix().to_string(),
+                region: listener_key.get_region().to_string(),
+                access_key_id: listener_key.get_access_key_id().to_string(),
+            },
+            job_ids: job_ids.iter().map(ToString::to_string).collect(),
+            objects,
+        };
+        // Write to a temporary file first so that a crash never leaves a partial batch behind.
+        let tmp_path = path.with_extension("tmp");
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&tmp_path)
+            .await?;
+        file.write_all(&serde_json::to_vec(&batch)?).await?;
+        file.sync_all().await?;
+        fs::rename(&tmp_path, &path).await?;
+
+        let objects: Vec<&S3Object> = batch.objects.iter().collect();
+        if let Err(e) = ledger::mark_spilled(&objects).await {
+            log::error!("[{tag}] Failed to record spilled objects in the ingestion ledger: {e}");
+        }
+        log::warn!(
+            "[{tag}] Spilled {} objects to {}.",
+            objects.len(),
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// Spawns a background task that periodically resubmits all spilled batches.
+    pub fn spawn_replayer(self: Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = self.replay().await {
+                    log::error!("Failed to replay spilled batches: {e}");
+                }
+                sleep(interval).await;
+            }
+        })
+    }
+
+    /// Resubmits spilled batches in spill order.
+    ///
+    /// A batch that fails to be resubmitted is left in the queue for the next replay, without
+    /// holding back the later batches.
+    async fn replay(&self) -> Result<()> {
+        let mut paths = Vec::new();
+        if fs::try_exists(&self.dir).await? {
+            let mut entries = fs::read_dir(&self.dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if path.extension().is_some_and(|ext| ext == "json") {
+                    paths.push(path);
+                }
+            }
+        }
+        paths.sort_unstable();
+
+        for path in paths {
+            if let Err(e) = replay_batch(&path).await {
+                log::error!("Failed to replay spilled batch {}: {e}", path.display());
+                metrics::increment("spilled_batch_replay_failures_total", &[]);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Resubmits a spilled batch, and removes it from the queue once submitted.
+async fn replay_batch(path: &Path) -> Result<()> {
+    let batch: SpilledBatch = match serde_json::from_slice(&fs::read(path).await?) {
+        Ok(batch) => batch,
+        Err(e) => {
+            log::error!("Failed to parse spilled batch {}: {e}", path.display());
+            return quarantine(path).await;
+        }
+    };
+
+    let listener = batch.listener;
+    let mut secret_access_key = None;
+    for job_id in &batch.job_ids {
+        if let Some(record) = jobs::fetch_one(Uuid::parse_str(job_id)?).await?
+            && record.access_key_id == listener.access_key_id
+        {
+            secret_access_key = Some(record.secret_access_key);
+            break;
+        }
+    }
+    let secret_access_key = secret_access_key.ok_or_else(|| {
+        anyhow!(
+            "None of the jobs {:?} that sent the batch's objects were found.",
+            batch.job_ids
+        )
+    })?;
+    let listener_key = ListenerKey::new(
+        listener.dataset,
+        listener.bucket,
+        listener.key_prefix,
+        listener.region,
+        listener.access_key_id,
+        secret_access_key.expose_secret().clone(),
+    );
+    let objects: Vec<&S3Object> = batch.objects.iter().collect();
+    let job_config = build_job_config(&listener_key, &objects);
+    let tag = listener_key.get_tag();
+    submit_and_track(tag.as_str(), job_config, &objects).await?;
+    metrics::increment(
+        "spilled_batches_replayed_total",
+        &[("buffer", tag.as_str())],
+    );
+    fs::remove_file(path).await?;
+    Ok(())
+}
+
+/// Renames an unreadable batch so that it's no longer replayed but kept for inspection.
+async fn quarantine(path: &Path) -> Result<()> {
+    fs::rename(path, path.with_extension("corrupt")).await?;
+    Ok(())
+}
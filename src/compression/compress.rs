@@ -1,6 +1,13 @@
+use std::collections::HashMap;
+
 use anyhow::Result;
+use sqlx::{MySql, QueryBuilder, Row};
 
-use super::config::JobConfig;
+use super::{CompressionJobStatus, config::JobConfig};
+use crate::{
+    database::{compression_jobs, ledger},
+    utils::S3Object,
+};
 
 pub async fn submit_compression_job(job_config: JobConfig) -> Result<u64> {
     let pool = crate::database::mysql::get_pool();
@@ -11,3 +18,84 @@ pub async fn submit_compression_job(job_config: JobConfig) -> Result<u64> {
 
     Ok(res.last_insert_id())
 }
+
+/// Submits a compression job and records it for tracking and in the ingestion ledger.
+///
+/// # Returns
+///
+/// The ID of the submitted compression job.
+pub async fn submit_and_track(
+    tag: &str,
+    job_config: JobConfig,
+    objects: &[&S3Object],
+) -> Result<u64> {
+    let dataset = job_config.input.dataset.clone();
+    let bucket = job_config.input.bucket.clone();
+    let compression_job_id = submit_compression_job(job_config).await?;
+    log::info!("[{tag}] Submitted compression job. Job ID: {compression_job_id}.");
+
+    if let Err(e) = compression_jobs::insert(
+        compression_job_id,
+        dataset.as_str(),
+        bucket.as_str(),
+        objects.len(),
+        objects.iter().map(|object| object.get_size()).sum(),
+    )
+    .await
+    {
+        log::error!("[{tag}] Failed to track compression job {compression_job_id}. Error: {e}");
+    }
+    if let Err(e) = ledger::mark_submitted(objects, compression_job_id).await {
+        log::error!(
+            "[{tag}] Failed to record submitted objects in the ingestion ledger. Error: {e}"
+        );
+    }
+    Ok(compression_job_id)
+}
+
+/// Fetches and decodes the config of the given compression job from CLP.
+pub async fn fetch_compression_job_config(id: u64) -> Result<Option<JobConfig>> {
+    let pool = crate::database::mysql::get_pool();
+    let row = sqlx::query(r"SELECT `clp_config` FROM compression_jobs WHERE `id` = ?")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await?;
+    row.map(|row| JobConfig::from_msgpack_brotli(row.try_get::<&[u8], _>("clp_config")?))
+        .transpose()
+}
+
+/// Fetches the current status and status message of the given compression jobs from CLP.
+///
+/// Jobs that don't exist in CLP are absent from the returned map.
+pub async fn fetch_compression_job_statuses(
+    ids: &[u64],
+) -> Result<HashMap<u64, (CompressionJobStatus, Option<String>)>> {
+    let mut statuses = HashMap::with_capacity(ids.len());
+    if ids.is_empty() {
+        return Ok(statuses);
+    }
+
+    let pool = crate::database::mysql::get_pool();
+    let mut builder: QueryBuilder<MySql> = QueryBuilder::new(
+        r"SELECT CAST(`id` AS UNSIGNED) AS `id`, `status`, `status_msg`
+            FROM compression_jobs
+            WHERE `id` IN (",
+    );
+    let mut separated = builder.separated(", ");
+    for id in ids {
+        separated.push_bind(*id);
+    }
+    separated.push_unseparated(")");
+
+    for row in builder.build().fetch_all(&pool).await? {
+        let status_msg: String = row.try_get("status_msg")?;
+        statuses.insert(
+            row.try_get("id")?,
+            (
+                CompressionJobStatus::from_clp(row.try_get("status")?)?,
+                Some(status_msg).filter(|msg| !msg.is_empty()),
+            ),
+        );
+    }
+    Ok(statuses)
+}
@@ -1,14 +1,14 @@
 use anyhow::Result;
-use brotli::CompressorWriter;
-use serde::Serialize;
+use brotli::{CompressorWriter, Decompressor};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct JobConfig {
     pub input: Input,
     pub output: Output,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Input {
     pub aws_authentication: AwsAuthentication,
     pub bucket: String,
@@ -18,20 +18,20 @@ pub struct Input {
     pub keys: Option<Vec<String>>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum AwsAuthentication {
     #[serde(rename = "credentials")]
     Credentials { credentials: AwsCredentials },
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AwsCredentials {
     pub access_key_id: String,
     pub secret_access_key: String,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Output {
     pub compression_level: u8,
     pub target_archive_size: u64,
@@ -48,6 +48,13 @@ impl JobConfig {
         let compressed_data = encoder.into_inner();
         Ok(compressed_data)
     }
+
+    /// Decodes a config encoded by [`Self::to_msgpack_brotli`].
+    pub fn from_msgpack_brotli(data: &[u8]) -> Result<Self> {
+        let mut msgpack_data = Vec::new();
+        std::io::copy(&mut Decompressor::new(data, 4096), &mut msgpack_data)?;
+        Ok(rmp_serde::from_slice(&msgpack_data)?)
+    }
 }
 
 #[cfg(test)]
@@ -88,6 +95,10 @@ mod tests {
                 54aa0a22993d5506cbd4ed40c2893f5b9d4ae34f1c77c3b6012b55c15e377ef337079519384986879b1\
                 11923d5105a4da2dc5a8865534b5e83aff5b1935ceaea26b0b8826562284efdf8a5890ef0d501fc9ff0\
                 8c3309fc553d8a3d16544c021fa6069c8829cf6b3c2b44901";
-        assert_eq!(expected, hex::encode(serialized));
+        assert_eq!(expected, hex::encode(&serialized));
+
+        let deserialized = JobConfig::from_msgpack_brotli(&serialized);
+        assert!(deserialized.is_ok());
+        assert_eq!(config, deserialized.unwrap());
     }
 }
@@ -1,4 +1,11 @@
 mod compress;
 pub mod config;
+mod status;
+pub mod tracker;
 
-pub use compress::submit_compression_job;
+pub use compress::{
+    fetch_compression_job_config,
+    fetch_compression_job_statuses,
+    submit_and_track,
+};
+pub use status::CompressionJobStatus;
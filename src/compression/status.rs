@@ -0,0 +1,70 @@
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+
+/// The status of a compression job, mirroring CLP's `CompressionJobStatus`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompressionJobStatus {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+    Killed,
+}
+
+impl CompressionJobStatus {
+    /// Converts the integer status stored in CLP's `compression_jobs` table.
+    pub fn from_clp(value: i32) -> Result<Self> {
+        match value {
+            0 => Ok(Self::Pending),
+            1 => Ok(Self::Running),
+            2 => Ok(Self::Succeeded),
+            3 => Ok(Self::Failed),
+            4 => Ok(Self::Killed),
+            _ => Err(anyhow!("Unknown CLP compression job status: {value}")),
+        }
+    }
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Running => "running",
+            Self::Succeeded => "succeeded",
+            Self::Failed => "failed",
+            Self::Killed => "killed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "pending" => Ok(Self::Pending),
+            "running" => Ok(Self::Running),
+            "succeeded" => Ok(Self::Succeeded),
+            "failed" => Ok(Self::Failed),
+            "killed" => Ok(Self::Killed),
+            _ => Err(anyhow!("Unknown compression job status: {value}")),
+        }
+    }
+
+    pub const fn is_terminal(self) -> bool {
+        matches!(self, Self::Succeeded | Self::Failed | Self::Killed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_conversions() {
+        for value in 0..5 {
+            let status = CompressionJobStatus::from_clp(value).unwrap();
+            assert_eq!(
+                status,
+                CompressionJobStatus::parse(status.as_str()).unwrap()
+            );
+            assert_eq!(status.is_terminal(), value >= 2);
+        }
+        assert!(CompressionJobStatus::from_clp(5).is_err());
+    }
+}
@@ -0,0 +1,307 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::{task::JoinHandle, time::sleep};
+
+use super::{
+    CompressionJobStatus,
+    fetch_compression_job_config,
+    fetch_compression_job_statuses,
+    submit_and_track,
+};
+use crate::{
+    database::{
+        compression_jobs,
+        ledger,
+        ledger::{IngestedObject, ObjectStatus},
+    },
+    metrics,
+    utils::S3Object,
+};
+
+/// Configuration for resubmitting objects whose compression job failed.
+#[derive(Clone, Copy)]
+pub struct ResubmissionConfig {
+    /// The number of failed compression jobs an object may be part of before it's quarantined.
+    pub max_retries: u32,
+    /// Whether to split a failed batch in halves to isolate objects that make CLP fail.
+    pub split_batches: bool,
+}
+
+impl ResubmissionConfig {
+    /// Returns whether the failure of a compression job of `num_objects` objects counts against
+    /// each of them.
+    ///
+    /// While failed batches are split, a failed batch may hold a single object making CLP fail, so
+    /// a failure only counts against an object that failed in a batch of its own.
+    pub const fn counts_failures(self, num_objects: usize) -> bool {
+        !self.split_batches || num_objects <= 1
+    }
+}
+
+/// Spawns a background task that periodically polls CLP for the status of every unfinished
+/// compression job submitted by the ingestor, records the outcome per job and per object, and
+/// resubmits the objects of failed jobs.
+pub fn spawn(poll_interval: Duration, resubmission_config: ResubmissionConfig) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = poll(resubmission_config).await {
+                log::error!("Failed to poll compression job statuses: {e:?}");
+            }
+            sleep(poll_interval).await;
+        }
+    })
+}
+
+async fn poll(resubmission_config: ResubmissionConfig) -> Result<()> {
+    let unfinished_jobs = compression_jobs::fetch_unfinished().await?;
+    if unfinished_jobs.is_empty() {
+        return Ok(());
+    }
+
+    let ids: Vec<u64> = unfinished_jobs.iter().map(|(id, _)| *id).collect();
+    let mut statuses = fetch_compression_job_statuses(&ids).await?;
+    for (id, recorded_status) in unfinished_jobs {
+        let Some((status, status_msg)) = statuses.remove(&id) else {
+            log::warn!("Compression job {id} not found in CLP.");
+            continue;
+        };
+        if status == recorded_status {
+            continue;
+        }
+        // A job whose outcome can't be recorded is retried in the next poll, without holding back
+        // the others.
+        if let Err(e) = record_status(id, status, status_msg, resubmission_config).await {
+            log::error!("Failed to record the status of compression job {id}: {e:?}");
+            metrics::increment("compression_job_tracking_failures_total", &[]);
+        }
+    }
+    Ok(())
+}
+
+/// Records the new status of a compression job, and the outcome of its objects.
+async fn record_status(
+    id: u64,
+    status: CompressionJobStatus,
+    status_msg: Option<String>,
+    resubmission_config: ResubmissionConfig,
+) -> Result<()> {
+    if status == CompressionJobStatus::Succeeded {
+        log::info!("Compression job {id} succeeded.");
+        ledger::update_status_by_compression_job(id, ObjectStatus::Succeeded).await?;
+    } else if status.is_terminal() {
+        log::error!(
+            "Compression job {id} {}: {}",
+            status.as_str(),
+            status_msg.as_deref().unwrap_or("no status message")
+        );
+        let num_objects = ledger::fetch_by_compression_job(id).await?.len();
+        ledger::record_failure(id, resubmission_config.counts_failures(num_objects)).await?;
+        // Killed jobs were stopped on purpose, so they aren't retried.
+        if status == CompressionJobStatus::Failed {
+            resubmit(id, resubmission_config).await?;
+        }
+    }
+    compression_jobs::update_status(id, status, status_msg.as_deref()).await
+}
+
+/// Resubmits the failed objects of the given compression job, quarantining the objects that
+/// exceeded the retry limit.
+async fn resubmit(compression_job_id: u64, config: ResubmissionConfig) -> Result<()> {
+    let failed_objects: Vec<IngestedObject> = ledger::fetch_by_compression_job(compression_job_id)
+        .await?
+        .into_iter()
+        .filter(|object| object.status == ObjectStatus::Failed)
+        .collect();
+    if failed_objects.is_empty() {
+        return Ok(());
+    }
+    let Some(job_config) = fetch_compression_job_config(compression_job_id).await? else {
+        log::error!("Config of compression job {compression_job_id} not found in CLP.");
+        return Ok(());
+    };
+    let tag = format!(
+        "{}-{}",
+        job_config.input.dataset.as_str(),
+        job_config.input.bucket.as_str()
+    );
+
+    let (quarantined, batches) = plan_resubmission(failed_objects, config);
+    if !quarantined.is_empty() {
+        let quarantined = to_s3_objects(quarantined)?;
+        log::error!(
+            "[{tag}] Quarantining objects that failed more than {} times: {quarantined:?}",
+            config.max_retries
+        );
+        ledger::quarantine(&quarantined.iter().collect::<Vec<_>>()).await?;
+        metrics::increment("objects_quarantined_total", &[("buffer", tag.as_str())]);
+    }
+
+    for batch in batches {
+        let objects = to_s3_objects(batch)?;
+        let mut job_config = job_config.clone();
+        job_config.input.keys = Some(
+            objects
+                .iter()
+                .map(|object| object.get_key().to_owned())
+                .collect(),
+        );
+        let resubmitted_id = submit_and_track(
+            tag.as_str(),
+            job_config,
+            &objects.iter().collect::<Vec<_>>(),
+        )
+        .await?;
+        log::info!(
+            "[{tag}] Resubmitted {} objects of failed compression job {compression_job_id} as \
+             compression job {resubmitted_id}.",
+            objects.len()
+        );
+        metrics::increment(
+            "compression_resubmissions_total",
+            &[("buffer", tag.as_str())],
+        );
+    }
+    Ok(())
+}
+
+/// Splits the failed objects of a compression job into the objects to quarantine and the batches
+/// to resubmit.
+///
+/// The retry limit applies to every object, including when failed batches are split in halves.
+/// Since failures of split batches only count once an object fails in a batch of its own, the
+/// objects of a batch being split are resubmitted until the ones making CLP fail are isolated.
+fn plan_resubmission(
+    failed_objects: Vec<IngestedObject>,
+    config: ResubmissionConfig,
+) -> (Vec<IngestedObject>, Vec<Vec<IngestedObject>>) {
+    let (quarantined, mut retried): (Vec<_>, Vec<_>) = failed_objects
+        .into_iter()
+        .partition(|object| object.num_failures > config.max_retries);
+    let batches = if retried.is_empty() {
+        Vec::new()
+    } else if config.split_batches && retried.len() > 1 {
+        // Every object gets another chance until it fails in a batch of its own, or reaches the
+        // retry limit.
+        let right = retried.split_off(retried.len() / 2);
+        vec![retried, right]
+    } else {
+        vec![retried]
+    };
+    (quarantined, batches)
+}
+
+fn to_s3_objects(objects: Vec<IngestedObject>) -> Result<Vec<S3Object>> {
+    objects
+        .into_iter()
+        .map(|object| {
+            Ok(S3Object::new(
+                object.bucket,
+                object.key,
+                usize::try_from(object.size)?,
+                Some(object.etag).filter(|etag| !etag.is_empty()),
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failed_object(key: &str, num_failures: u32) -> IngestedObject {
+        IngestedObject {
+            bucket: "bucket".to_owned(),
+            key: key.to_owned(),
+            etag: String::new(),
+            size: 1,
+            compression_job_id: Some(1),
+            status: ObjectStatus::Failed,
+            num_failures,
+        }
+    }
+
+    fn get_keys(objects: &[IngestedObject]) -> Vec<&str> {
+        objects.iter().map(|object| object.key.as_str()).collect()
+    }
+
+    #[test]
+    fn test_plan_resubmission() {
+        let failed_objects = || {
+            vec![
+                failed_object("a", 1),
+                failed_object("b", 4),
+                failed_object("c", 2),
+                failed_object("d", 3),
+            ]
+        };
+
+        let (quarantined, batches) = plan_resubmission(
+            failed_objects(),
+            ResubmissionConfig {
+                max_retries: 3,
+                split_batches: false,
+            },
+        );
+        assert_eq!(get_keys(&quarantined), vec!["b"]);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(get_keys(&batches[0]), vec!["a", "c", "d"]);
+
+        // Objects past the limit are quarantined before the batch is split.
+        let (quarantined, batches) = plan_resubmission(
+            failed_objects(),
+            ResubmissionConfig {
+                max_retries: 3,
+                split_batches: true,
+            },
+        );
+        assert_eq!(get_keys(&quarantined), vec!["b"]);
+        assert_eq!(batches.len(), 2);
+        assert_eq!(get_keys(&batches[0]), vec!["a"]);
+        assert_eq!(get_keys(&batches[1]), vec!["c", "d"]);
+
+        let (quarantined, batches) = plan_resubmission(
+            vec![failed_object("a", 4), failed_object("b", 5)],
+            ResubmissionConfig {
+                max_retries: 3,
+                split_batches: true,
+            },
+        );
+        assert_eq!(get_keys(&quarantined), vec!["a", "b"]);
+        assert!(batches.is_empty());
+    }
+
+    #[test]
+    fn test_isolate_poison_object() {
+        let config = ResubmissionConfig {
+            max_retries: 3,
+            split_batches: true,
+        };
+        let mut batches = vec![
+            (0..16)
+                .map(|idx| failed_object(format!("{idx:02}").as_str(), 0))
+                .collect::<Vec<_>>(),
+        ];
+        let mut quarantined_keys = Vec::new();
+        let mut num_succeeded = 0;
+        while let Some(mut batch) = batches.pop() {
+            // Only the compression jobs including the poison object fail.
+            if batch.iter().all(|object| object.key != "05") {
+                num_succeeded += batch.len();
+                continue;
+            }
+            if config.counts_failures(batch.len()) {
+                for object in &mut batch {
+                    object.num_failures += 1;
+                }
+            }
+            let (quarantined, resubmitted) = plan_resubmission(batch, config);
+            quarantined_keys.extend(quarantined.into_iter().map(|object| object.key));
+            batches.extend(resubmitted);
+        }
+
+        // The healthy objects failing alongside the poison object are never quarantined.
+        assert_eq!(quarantined_keys, vec!["05"]);
+        assert_eq!(num_succeeded, 15);
+    }
+}
@@ -0,0 +1,48 @@
+use anyhow::Result;
+use sqlx::{MySqlPool, Row};
+use uuid::Uuid;
+
+pub async fn create_table(pool: &MySqlPool) -> Result<()> {
+    sqlx::query(
+        r"CREATE TABLE IF NOT EXISTS `ingestor_scan_checkpoints` (
+            `job_id` CHAR(36) NOT NULL,
+            `key_prefix` VARCHAR(512) NOT NULL,
+            `last_key` VARCHAR(1024) NOT NULL,
+            `update_time` DATETIME(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3)
+                ON UPDATE CURRENT_TIMESTAMP(3),
+            PRIMARY KEY (`job_id`, `key_prefix`)
+        )",
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Fetches the last key ingested by the given scanner job under the given prefix, if any.
+pub async fn fetch(job_id: Uuid, key_prefix: &str) -> Result<Option<String>> {
+    let pool = super::mysql::get_pool();
+    let row = sqlx::query(
+        r"SELECT `last_key` FROM `ingestor_scan_checkpoints`
+            WHERE `job_id` = ? AND `key_prefix` = ?",
+    )
+    .bind(job_id.to_string())
+    .bind(key_prefix)
+    .fetch_optional(&pool)
+    .await?;
+    Ok(row.map(|row| row.try_get("last_key")).transpose()?)
+}
+
+pub async fn upsert(job_id: Uuid, key_prefix: &str, last_key: &str) -> Result<()> {
+    let pool = super::mysql::get_pool();
+    sqlx::query(
+        r"INSERT INTO `ingestor_scan_checkpoints` (`job_id`, `key_prefix`, `last_key`)
+            VALUES (?, ?, ?)
+            ON DUPLICATE KEY UPDATE `last_key` = VALUES(`last_key`)",
+    )
+    .bind(job_id.to_string())
+    .bind(key_prefix)
+    .bind(last_key)
+    .execute(&pool)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,160 @@
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::{MySqlPool, Row};
+
+use crate::compression::CompressionJobStatus;
+
+/// A compression job submitted by the ingestor, as recorded in the tracking table.
+#[derive(Debug, Serialize)]
+pub struct TrackedCompressionJob {
+    pub id: u64,
+    pub dataset: String,
+    pub bucket: String,
+    pub num_objects: u64,
+    pub total_size: u64,
+    pub status: CompressionJobStatus,
+    pub status_msg: Option<String>,
+    pub submission_time: String,
+    pub completion_time: Option<String>,
+}
+
+pub async fn create_table(pool: &MySqlPool) -> Result<()> {
+    sqlx::query(
+        r"CREATE TABLE IF NOT EXISTS `ingestor_compression_jobs` (
+            `id` BIGINT UNSIGNED NOT NULL,
+            `dataset` VARCHAR(255) NOT NULL,
+            `bucket` VARCHAR(63) NOT NULL,
+            `num_objects` BIGINT UNSIGNED NOT NULL,
+            `total_size` BIGINT UNSIGNED NOT NULL,
+            `status` VARCHAR(16) NOT NULL,
+            `status_msg` TEXT NULL,
+            `submission_time` DATETIME(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+            `completion_time` DATETIME(3) NULL,
+            PRIMARY KEY (`id`),
+            INDEX `status` (`status`)
+        )",
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Starts tracking a newly submitted compression job.
+pub async fn insert(
+    id: u64,
+    dataset: &str,
+    bucket: &str,
+    num_objects: usize,
+    total_size: usize,
+) -> Result<()> {
+    let pool = super::mysql::get_pool();
+    sqlx::query(
+        r"INSERT INTO `ingestor_compression_jobs`
+            (`id`, `dataset`, `bucket`, `num_objects`, `total_size`, `status`)
+            VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(id)
+    .bind(dataset)
+    .bind(bucket)
+    .bind(num_objects as u64)
+    .bind(total_size as u64)
+    .bind(CompressionJobStatus::Pending.as_str())
+    .execute(&pool)
+    .await?;
+    Ok(())
+}
+
+/// Fetches the IDs and recorded statuses of all tracked jobs that haven't finished yet.
+pub async fn fetch_unfinished() -> Result<Vec<(u64, CompressionJobStatus)>> {
+    let pool = super::mysql::get_pool();
+    let rows = sqlx::query(
+        r"SELECT `id`, `status` FROM `ingestor_compression_jobs` WHERE `status` IN (?, ?)",
+    )
+    .bind(CompressionJobStatus::Pending.as_str())
+    .bind(CompressionJobStatus::Running.as_str())
+    .fetch_all(&pool)
+    .await?;
+
+    let mut jobs = Vec::with_capacity(rows.len());
+    for row in rows {
+        jobs.push((
+            row.try_get("id")?,
+            CompressionJobStatus::parse(row.try_get("status")?)?,
+        ));
+    }
+    Ok(jobs)
+}
+
+pub async fn update_status(
+    id: u64,
+    status: CompressionJobStatus,
+    status_msg: Option<&str>,
+) -> Result<()> {
+    let pool = super::mysql::get_pool();
+    sqlx::query(
+        r"UPDATE `ingestor_compression_jobs`
+            SET `status` = ?,
+                `status_msg` = ?,
+                `completion_time` = IF(?, CURRENT_TIMESTAMP(3), NULL)
+            WHERE `id` = ?",
+    )
+    .bind(status.as_str())
+    .bind(status_msg)
+    .bind(status.is_terminal())
+    .bind(id)
+    .execute(&pool)
+    .await?;
+    Ok(())
+}
+
+/// Fetches the most recently submitted tracked jobs, optionally filtered by status.
+pub async fn fetch(
+    status: Option<CompressionJobStatus>,
+    limit: u32,
+) -> Result<Vec<TrackedCompressionJob>> {
+    let pool = super::mysql::get_pool();
+    let rows = sqlx::query(
+        r"SELECT `id`, `dataset`, `bucket`, `num_objects`, `total_size`, `status`, `status_msg`,
+                CAST(`submission_time` AS CHAR) AS `submission_time`,
+                CAST(`completion_time` AS CHAR) AS `completion_time`
+            FROM `ingestor_compression_jobs`
+            WHERE ? IS NULL OR `status` = ?
+            ORDER BY `id` DESC
+            LIMIT ?",
+    )
+    .bind(status.map(CompressionJobStatus::as_str))
+    .bind(status.map(CompressionJobStatus::as_str))
+    .bind(limit)
+    .fetch_all(&pool)
+    .await?;
+    rows.iter().map(parse_row).collect()
+}
+
+pub async fn fetch_one(id: u64) -> Result<Option<TrackedCompressionJob>> {
+    let pool = super::mysql::get_pool();
+    let row = sqlx::query(
+        r"SELECT `id`, `dataset`, `bucket`, `num_objects`, `total_size`, `status`, `status_msg`,
+                CAST(`submission_time` AS CHAR) AS `submission_time`,
+                CAST(`completion_time` AS CHAR) AS `completion_time`
+            FROM `ingestor_compression_jobs`
+            WHERE `id` = ?",
+    )
+    .bind(id)
+    .fetch_optional(&pool)
+    .await?;
+    row.as_ref().map(parse_row).transpose()
+}
+
+fn parse_row(row: &sqlx::mysql::MySqlRow) -> Result<TrackedCompressionJob> {
+    Ok(TrackedCompressionJob {
+        id: row.try_get("id")?,
+        dataset: row.try_get("dataset")?,
+        bucket: row.try_get("bucket")?,
+        num_objects: row.try_get("num_objects")?,
+        total_size: row.try_get("total_size")?,
+        status: CompressionJobStatus::parse(row.try_get("status")?)?,
+        status_msg: row.try_get("status_msg")?,
+        submission_time: row.try_get("submission_time")?,
+        completion_time: row.try_get("completion_time")?,
+    })
+}
@@ -0,0 +1,117 @@
+use std::{path::Path, sync::OnceLock};
+
+use aes_gcm::{
+    Aes256Gcm,
+    Nonce,
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+};
+use anyhow::{Result, anyhow};
+use secrecy::{ExposeSecret, SecretString};
+
+static CIPHER: OnceLock<Aes256Gcm> = OnceLock::new();
+
+/// The size of the nonce prepended to every encrypted secret, in bytes.
+const NONCE_SIZE: usize = 12;
+
+/// Loads the key used to encrypt the secrets persisted in the database.
+///
+/// The file must contain a hex-encoded 256-bit key, e.g., as generated by `openssl rand -hex 32`.
+pub fn init(key_file: &Path) -> Result<()> {
+    let key = std::fs::read_to_string(key_file)?;
+    CIPHER
+        .set(create_cipher(key.trim())?)
+        .map_err(|_| anyhow!("Failed to set credential cipher"))?;
+    Ok(())
+}
+
+/// Returns whether a key to encrypt secrets with has been loaded.
+pub fn is_initialized() -> bool {
+    CIPHER.get().is_some()
+}
+
+/// Encrypts a secret for persistence.
+///
+/// # Returns
+///
+/// The hex-encoded nonce followed by the ciphertext.
+///
+/// # Errors
+///
+/// Returns an error if no key has been loaded.
+pub fn encrypt(secret: &SecretString) -> Result<String> {
+    encrypt_with(get_cipher()?, secret)
+}
+
+/// Decrypts a secret encrypted by [`encrypt`].
+///
+/// # Errors
+///
+/// Returns an error if no key has been loaded, or the secret wasn't encrypted with it.
+pub fn decrypt(encrypted: &str) -> Result<SecretString> {
+    decrypt_with(get_cipher()?, encrypted)
+}
+
+fn get_cipher() -> Result<&'static Aes256Gcm> {
+    CIPHER
+        .get()
+        .ok_or_else(|| anyhow!("No credential key is loaded. Set --credential-key-file."))
+}
+
+fn create_cipher(key_hex: &str) -> Result<Aes256Gcm> {
+    Aes256Gcm::new_from_slice(&hex::decode(key_hex)?)
+        .map_err(|_| anyhow!("The credential key must be 256 bits long."))
+}
+
+fn encrypt_with(cipher: &Aes256Gcm, secret: &SecretString) -> Result<String> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, secret.expose_secret().as_bytes())
+        .map_err(|_| anyhow!("Failed to encrypt secret."))?;
+    let mut encrypted = nonce.to_vec();
+    encrypted.extend(ciphertext);
+    Ok(hex::encode(encrypted))
+}
+
+fn decrypt_with(cipher: &Aes256Gcm, encrypted: &str) -> Result<SecretString> {
+    let encrypted = hex::decode(encrypted)?;
+    if encrypted.len() < NONCE_SIZE {
+        return Err(anyhow!("Encrypted secret is too short."));
+    }
+    let (nonce, ciphertext) = encrypted.split_at(NONCE_SIZE);
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow!("Failed to decrypt secret. Was it encrypted with another key?"))?;
+    Ok(SecretString::from(String::from_utf8(plaintext)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    #[test]
+    fn test_encryption() -> Result<()> {
+        let cipher = create_cipher(KEY)?;
+        let secret = SecretString::from("SECRET_ACCESS_KEY".to_owned());
+
+        let encrypted = encrypt_with(&cipher, &secret)?;
+        assert!(!encrypted.contains(hex::encode("SECRET_ACCESS_KEY").as_str()));
+        assert_ne!(encrypted, encrypt_with(&cipher, &secret)?);
+        assert_eq!(
+            decrypt_with(&cipher, encrypted.as_str())?.expose_secret(),
+            "SECRET_ACCESS_KEY"
+        );
+
+        let other_cipher = create_cipher(&KEY.replace("00", "ff"))?;
+        assert!(decrypt_with(&other_cipher, encrypted.as_str()).is_err());
+        assert!(decrypt_with(&cipher, "00").is_err());
+        assert!(create_cipher("0001").is_err());
+
+        // The key is never loaded by tests, so secrets can't be encrypted or decrypted.
+        assert!(!is_initialized());
+        assert!(encrypt(&secret).is_err());
+        assert!(decrypt(encrypted.as_str()).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::{MySqlPool, Row};
+use uuid::Uuid;
+
+/// An SQS message that couldn't be parsed and has been moved out of its queue.
+#[derive(Debug, Serialize)]
+pub struct DeadLetter {
+    pub id: u64,
+    pub job_id: String,
+    pub message_id: Option<String>,
+    pub message_group_id: Option<String>,
+    pub body: String,
+    pub error: String,
+    pub receive_count: u32,
+    pub creation_time: String,
+}
+
+pub async fn create_table(pool: &MySqlPool) -> Result<()> {
+    sqlx::query(
+        r"CREATE TABLE IF NOT EXISTS `ingestor_dead_letters` (
+            `id` BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+            `job_id` CHAR(36) NOT NULL,
+            `message_id` VARCHAR(128) NULL,
+            `message_group_id` VARCHAR(128) NULL,
+            `body` MEDIUMTEXT NOT NULL,
+            `error` TEXT NOT NULL,
+            `receive_count` INT UNSIGNED NOT NULL,
+            `creation_time` DATETIME(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+            PRIMARY KEY (`id`),
+            INDEX `job_id` (`job_id`)
+        )",
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn insert(
+    job_id: Uuid,
+    message_id: Option<&str>,
+    message_group_id: Option<&str>,
+    body: &str,
+    error: &str,
+    receive_count: u32,
+) -> Result<()> {
+    let pool = super::mysql::get_pool();
+    sqlx::query(
+        r"INSERT INTO `ingestor_dead_letters`
+            (`job_id`, `message_id`, `message_group_id`, `body`, `error`, `receive_count`)
+            VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(job_id.to_string())
+    .bind(message_id)
+    .bind(message_group_id)
+    .bind(body)
+    .bind(error)
+    .bind(receive_count)
+    .execute(&pool)
+    .await?;
+    Ok(())
+}
+
+/// Fetches the most recent dead letters, optionally filtered by job.
+pub async fn fetch(job_id: Option<Uuid>, limit: u32) -> Result<Vec<DeadLetter>> {
+    let pool = super::mysql::get_pool();
+    let job_id = job_id.map(|id| id.to_string());
+    let rows = sqlx::query(
+        r"SELECT `id`, `job_id`, `message_id`, `message_group_id`, `body`, `error`, `receive_count`,
+                CAST(`creation_time` AS CHAR) AS `creation_time`
+            FROM `ingestor_dead_letters`
+            WHERE ? IS NULL OR `job_id` = ?
+            ORDER BY `id` DESC
+            LIMIT ?",
+    )
+    .bind(job_id.as_deref())
+    .bind(job_id.as_deref())
+    .bind(limit)
+    .fetch_all(&pool)
+    .await?;
+    rows.iter().map(parse_row).collect()
+}
+
+pub async fn fetch_one(id: u64) -> Result<Option<DeadLetter>> {
+    let pool = super::mysql::get_pool();
+    let row = sqlx::query(
+        r"SELECT `id`, `job_id`, `message_id`, `message_group_id`, `body`, `error`, `receive_count`,
+                CAST(`creation_time` AS CHAR) AS `creation_time`
+            FROM `ingestor_dead_letters`
+            WHERE `id` = ?",
+    )
+    .bind(id)
+    .fetch_optional(&pool)
+    .await?;
+    row.as_ref().map(parse_row).transpose()
+}
+
+pub async fn delete(id: u64) -> Result<()> {
+    let pool = super::mysql::get_pool();
+    sqlx::query(r"DELETE FROM `ingestor_dead_letters` WHERE `id` = ?")
+        .bind(id)
+        .execute(&pool)
+        .await?;
+    Ok(())
+}
+
+fn parse_row(row: &sqlx::mysql::MySqlRow) -> Result<DeadLetter> {
+    Ok(DeadLetter {
+        id: row.try_get("id")?,
+        job_id: row.try_get("job_id")?,
+        message_id: row.try_get("message_id")?,
+        message_group_id: row.try_get("message_group_id")?,
+        body: row.try_get("body")?,
+        error: row.try_get("error")?,
+        receive_count: row.try_get("receive_count")?,
+        creation_time: row.try_get("creation_time")?,
+    })
+}
@@ -0,0 +1,216 @@
+use anyhow::{Result, anyhow};
+use secrecy::SecretString;
+use sqlx::{MySqlPool, Row};
+use uuid::Uuid;
+
+/// The type of an ingestion job persisted in the job table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobType {
+    Scanner,
+    SqsListener,
+    Backfill,
+}
+
+/// The lifecycle status of an ingestion job persisted in the job table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobStatus {
+    Active,
+    Cancelled,
+    /// A one-shot job that has finished.
+    Completed,
+    /// A one-shot job that stopped on an error.
+    Failed,
+}
+
+/// A row of the job table.
+pub struct JobRecord {
+    pub id: Uuid,
+    pub job_type: JobType,
+    pub params: String,
+    pub access_key_id: String,
+    pub secret_access_key: SecretString,
+}
+
+impl JobType {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Scanner => "scanner",
+            Self::SqsListener => "sqs_listener",
+            Self::Backfill => "backfill",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self> {
+        match value {
+            "scanner" => Ok(Self::Scanner),
+            "sqs_listener" => Ok(Self::SqsListener),
+            "backfill" => Ok(Self::Backfill),
+            _ => Err(anyhow!("Unknown job type: {value}")),
+        }
+    }
+}
+
+impl JobStatus {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Active => "active",
+            Self::Cancelled => "cancelled",
+            Self::Completed => "completed",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+pub async fn create_table(pool: &MySqlPool) -> Result<()> {
+    sqlx::query(
+        r"CREATE TABLE IF NOT EXISTS `ingestor_jobs` (
+            `id` CHAR(36) NOT NULL,
+            `type` VARCHAR(32) NOT NULL,
+            `params` TEXT NOT NULL,
+            `access_key_id` VARCHAR(128) NOT NULL,
+            `encrypted_secret_access_key` VARCHAR(512) NOT NULL,
+            `status` VARCHAR(16) NOT NULL,
+            `creation_time` DATETIME(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+            `update_time` DATETIME(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3)
+                ON UPDATE CURRENT_TIMESTAMP(3),
+            PRIMARY KEY (`id`),
+            INDEX `status` (`status`)
+        )",
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Migrates a job table created by an earlier version, which persisted secret access keys in
+/// plaintext, encrypting them if a credential key is loaded.
+///
+/// Without a credential key, the plaintext secrets are left in place, and the jobs holding them
+/// can't be restored until the server is restarted with one.
+pub async fn migrate_plaintext_secrets(pool: &MySqlPool) -> Result<()> {
+    let columns = super::mysql::fetch_columns(pool, "ingestor_jobs").await?;
+    if !columns.contains("secret_access_key") {
+        return Ok(());
+    }
+    if !columns.contains("encrypted_secret_access_key") {
+        sqlx::query(
+            r"ALTER TABLE `ingestor_jobs`
+                ADD COLUMN `encrypted_secret_access_key` VARCHAR(512) NOT NULL DEFAULT ''
+                    AFTER `access_key_id`,
+                MODIFY COLUMN `secret_access_key` VARCHAR(256) NULL",
+        )
+        .execute(pool)
+        .await?;
+    }
+    if !super::credentials::is_initialized() {
+        log::warn!(
+            "The job table holds plaintext secrets persisted by an earlier version. Restart the \
+             server with --credential-key-file to encrypt them and restore their jobs."
+        );
+        return Ok(());
+    }
+
+    let rows = sqlx::query(
+        r"SELECT `id`, `secret_access_key`
+            FROM `ingestor_jobs`
+            WHERE `secret_access_key` IS NOT NULL",
+    )
+    .fetch_all(pool)
+    .await?;
+    for row in &rows {
+        let secret_access_key = SecretString::from(row.try_get::<String, _>("secret_access_key")?);
+        sqlx::query(
+            r"UPDATE `ingestor_jobs`
+                SET `encrypted_secret_access_key` = ?, `secret_access_key` = NULL
+                WHERE `id` = ?",
+        )
+        .bind(super::credentials::encrypt(&secret_access_key)?)
+        .bind(row.try_get::<&str, _>("id")?)
+        .execute(pool)
+        .await?;
+    }
+    sqlx::query(r"ALTER TABLE `ingestor_jobs` DROP COLUMN `secret_access_key`")
+        .execute(pool)
+        .await?;
+    log::info!(
+        "Encrypted the plaintext secrets of {} jobs persisted by an earlier version.",
+        rows.len()
+    );
+    Ok(())
+}
+
+/// Inserts an active job. The secret access key is encrypted with the key loaded by
+/// [`super::credentials::init`].
+pub async fn insert(record: &JobRecord) -> Result<()> {
+    let pool = super::mysql::get_pool();
+    sqlx::query(
+        r"INSERT INTO `ingestor_jobs`
+            (`id`, `type`, `params`, `access_key_id`, `encrypted_secret_access_key`, `status`)
+            VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(record.id.to_string())
+    .bind(record.job_type.as_str())
+    .bind(record.params.as_str())
+    .bind(record.access_key_id.as_str())
+    .bind(super::credentials::encrypt(&record.secret_access_key)?)
+    .bind(JobStatus::Active.as_str())
+    .execute(&pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn update_status(id: Uuid, status: JobStatus) -> Result<()> {
+    let pool = super::mysql::get_pool();
+    sqlx::query(r"UPDATE `ingestor_jobs` SET `status` = ? WHERE `id` = ?")
+        .bind(status.as_str())
+        .bind(id.to_string())
+        .execute(&pool)
+        .await?;
+    Ok(())
+}
+
+/// Fetches all jobs that should be running, in creation order.
+///
+/// # Returns
+///
+/// The result of parsing each job, so that a job that can't be parsed or whose secret can't be
+/// decrypted doesn't prevent the others from being restored.
+pub async fn fetch_active() -> Result<Vec<Result<JobRecord>>> {
+    let pool = super::mysql::get_pool();
+    let rows = sqlx::query(
+        r"SELECT `id`, `type`, `params`, `access_key_id`, `encrypted_secret_access_key`
+            FROM `ingestor_jobs`
+            WHERE `status` = ?
+            ORDER BY `creation_time`",
+    )
+    .bind(JobStatus::Active.as_str())
+    .fetch_all(&pool)
+    .await?;
+    Ok(rows.iter().map(parse_row).collect())
+}
+
+/// Fetches the job with the given ID, regardless of its status.
+pub async fn fetch_one(id: Uuid) -> Result<Option<JobRecord>> {
+    let pool = super::mysql::get_pool();
+    let row = sqlx::query(
+        r"SELECT `id`, `type`, `params`, `access_key_id`, `encrypted_secret_access_key`
+            FROM `ingestor_jobs`
+            WHERE `id` = ?",
+    )
+    .bind(id.to_string())
+    .fetch_optional(&pool)
+    .await?;
+    row.as_ref().map(parse_row).transpose()
+}
+
+fn parse_row(row: &sqlx::mysql::MySqlRow) -> Result<JobRecord> {
+    let id = Uuid::parse_str(row.try_get::<&str, _>("id")?)?;
+    Ok(JobRecord {
+        id,
+        job_type: JobType::parse(row.try_get("type")?)?,
+        params: row.try_get("params")?,
+        access_key_id: row.try_get("access_key_id")?,
+        secret_access_key: super::credentials::decrypt(row.try_get("encrypted_secret_access_key")?)
+            .map_err(|e| anyhow!("Failed to decrypt the secret access key of job {id}: {e}"))?,
+    })
+}
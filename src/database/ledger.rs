@@ -0,0 +1,441 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::OnceLock,
+};
+
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{MySql, MySqlPool, QueryBuilder, Row};
+
+use crate::utils::S3Object;
+
+/// The maximum number of objects written or looked up by a single bulk statement.
+const BULK_CHUNK_SIZE: usize = 1000;
+
+/// The ID of this ingestor among the ingestors sharing the database, recorded with its claims.
+static INSTANCE_ID: OnceLock<String> = OnceLock::new();
+
+/// The ingestion status of an object recorded in the ledger.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObjectStatus {
+    /// The object is held by a buffer and hasn't been submitted yet.
+    Buffered,
+    /// The object's submission kept failing and it has been spilled to the local spill queue.
+    Spilled,
+    /// The object has been submitted in a compression job.
+    Submitted,
+    /// The compression job of the object succeeded.
+    Succeeded,
+    /// The compression job of the object failed or was killed.
+    Failed,
+    /// The object's compression jobs kept failing and it won't be retried anymore.
+    Quarantined,
+}
+
+/// An entry of the ingestion ledger.
+#[derive(Debug, Serialize)]
+pub struct IngestedObject {
+    pub bucket: String,
+    pub key: String,
+    pub etag: String,
+    pub size: u64,
+    pub compression_job_id: Option<u64>,
+    pub status: ObjectStatus,
+    pub num_failures: u32,
+}
+
+impl ObjectStatus {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Buffered => "buffered",
+            Self::Spilled => "spilled",
+            Self::Submitted => "submitted",
+            Self::Succeeded => "succeeded",
+            Self::Failed => "failed",
+            Self::Quarantined => "quarantined",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self> {
+        match value {
+            "buffered" => Ok(Self::Buffered),
+            "spilled" => Ok(Self::Spilled),
+            "submitted" => Ok(Self::Submitted),
+            "succeeded" => Ok(Self::Succeeded),
+            "failed" => Ok(Self::Failed),
+            "quarantined" => Ok(Self::Quarantined),
+            _ => Err(anyhow!("Unknown object status: {value}")),
+        }
+    }
+}
+
+pub async fn create_table(pool: &MySqlPool) -> Result<()> {
+    sqlx::query(
+        r"CREATE TABLE IF NOT EXISTS `ingestor_ingested_objects` (
+            `id` BINARY(32) NOT NULL,
+            `bucket` VARCHAR(63) NOT NULL,
+            `key` VARCHAR(1024) NOT NULL,
+            `etag` VARCHAR(64) NOT NULL,
+            `size` BIGINT UNSIGNED NOT NULL,
+            `compression_job_id` BIGINT UNSIGNED NULL,
+            `status` VARCHAR(16) NOT NULL,
+            `num_failures` INT UNSIGNED NOT NULL DEFAULT 0,
+            `claimed_by` VARCHAR(64) NULL,
+            `claim_id` BINARY(16) NULL,
+            `update_time` DATETIME(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3)
+                ON UPDATE CURRENT_TIMESTAMP(3),
+            PRIMARY KEY (`id`),
+            INDEX `location` (`bucket`, `key`(700)),
+            INDEX `compression_job_id` (`compression_job_id`),
+            INDEX `status` (`status`)
+        )",
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Sets up the ledger for this ingestor, migrating a table created by an earlier version.
+///
+/// The objects claimed by the buffers of a previous run of this ingestor are released, since those
+/// buffers are gone and the objects must be ingestible again. The claims of other ingestors sharing
+/// the database are left alone, since their buffers may still hold the objects.
+pub async fn init(pool: &MySqlPool, instance_id: &str) -> Result<()> {
+    if instance_id.is_empty() || instance_id.len() > 64 {
+        return Err(anyhow!("The instance ID must be 1 to 64 bytes long."));
+    }
+    if !super::mysql::fetch_columns(pool, "ingestor_ingested_objects")
+        .await?
+        .contains("claimed_by")
+    {
+        sqlx::query(
+            r"ALTER TABLE `ingestor_ingested_objects`
+                ADD COLUMN `claimed_by` VARCHAR(64) NULL AFTER `num_failures`,
+                ADD COLUMN `claim_id` BINARY(16) NULL AFTER `claimed_by`",
+        )
+        .execute(pool)
+        .await?;
+    }
+    INSTANCE_ID
+        .set(instance_id.to_string())
+        .map_err(|_| anyhow!("Failed to set ledger instance ID"))?;
+
+    // Claims without an owner were made by earlier versions, which released every claim on
+    // startup.
+    let res = sqlx::query(
+        r"DELETE FROM `ingestor_ingested_objects`
+            WHERE `status` = ? AND (`claimed_by` = ? OR `claimed_by` IS NULL)",
+    )
+    .bind(ObjectStatus::Buffered.as_str())
+    .bind(instance_id)
+    .execute(pool)
+    .await?;
+    if res.rows_affected() > 0 {
+        log::info!(
+            "Released {} objects buffered by a previous run.",
+            res.rows_affected()
+        );
+    }
+    Ok(())
+}
+
+/// Claims the given objects for ingestion.
+///
+/// # Returns
+///
+/// For each object, `None` if it was claimed, or the status of the same object (same bucket, key,
+/// etag, and size) if it has already been claimed or ingested. An object given more than once is
+/// only claimed the first time.
+pub async fn claim(objects: &[&S3Object]) -> Result<Vec<Option<ObjectStatus>>> {
+    let pool = super::mysql::get_pool();
+    let instance_id = INSTANCE_ID
+        .get()
+        .ok_or_else(|| anyhow!("The ledger is not initialized."))?;
+    // Identifies the rows inserted by this call, as opposed to the ones that already existed.
+    let claim_id = uuid::Uuid::new_v4().as_bytes().to_vec();
+    let ids: Vec<Vec<u8>> = objects.iter().map(|object| get_object_id(object)).collect();
+    let mut statuses = HashMap::new();
+    for (chunk, chunk_ids) in objects
+        .chunks(BULK_CHUNK_SIZE)
+        .zip(ids.chunks(BULK_CHUNK_SIZE))
+    {
+        let mut builder: QueryBuilder<MySql> = QueryBuilder::new(
+            r"INSERT IGNORE INTO `ingestor_ingested_objects`
+                (`id`, `bucket`, `key`, `etag`, `size`, `status`, `claimed_by`, `claim_id`) ",
+        );
+        builder.push_values(chunk.iter().zip(chunk_ids), |mut row, (object, id)| {
+            row.push_bind(id)
+                .push_bind(object.get_bucket())
+                .push_bind(object.get_key())
+                .push_bind(object.get_etag().unwrap_or_default())
+                .push_bind(object.get_size() as u64)
+                .push_bind(ObjectStatus::Buffered.as_str())
+                .push_bind(instance_id)
+                .push_bind(&claim_id);
+        });
+        builder.build().execute(&pool).await?;
+
+        let mut builder: QueryBuilder<MySql> = QueryBuilder::new(
+            r"SELECT `id`, `status`, `claim_id` FROM `ingestor_ingested_objects` WHERE `id` IN (",
+        );
+        let mut separated = builder.separated(", ");
+        for id in chunk_ids {
+            separated.push_bind(id);
+        }
+        builder.push(")");
+        for row in builder.build().fetch_all(&pool).await? {
+            let status =
+                if row.try_get::<Option<Vec<u8>>, _>("claim_id")?.as_ref() == Some(&claim_id) {
+                    None
+                } else {
+                    Some(ObjectStatus::parse(row.try_get("status")?)?)
+                };
+            statuses.insert(row.try_get::<Vec<u8>, _>("id")?, status);
+        }
+    }
+
+    let mut claimed = HashSet::new();
+    Ok(ids
+        .into_iter()
+        .map(|id| {
+            // An object whose claim was released in the meantime is buffered as if it was claimed.
+            let status = statuses.get(&id).copied().flatten();
+            if status.is_none() && !claimed.insert(id) {
+                return Some(ObjectStatus::Buffered);
+            }
+            status
+        })
+        .collect())
+}
+
+/// Releases the claim on the given object, as long as it hasn't been submitted yet.
+pub async fn release(object: &S3Object) -> Result<()> {
+    let pool = super::mysql::get_pool();
+    sqlx::query(r"DELETE FROM `ingestor_ingested_objects` WHERE `id` = ? AND `status` = ?")
+        .bind(get_object_id(object))
+        .bind(ObjectStatus::Buffered.as_str())
+        .execute(&pool)
+        .await?;
+    Ok(())
+}
+
+/// Records the given objects as submitted in the given compression job.
+///
+/// Objects that were never claimed are recorded as well.
+pub async fn mark_submitted(objects: &[&S3Object], compression_job_id: u64) -> Result<()> {
+    upsert(objects, Some(compression_job_id), ObjectStatus::Submitted).await
+}
+
+/// Records the given objects as spilled to the local spill queue.
+///
+/// Objects that were never claimed are recorded as well.
+pub async fn mark_spilled(objects: &[&S3Object]) -> Result<()> {
+    upsert(objects, None, ObjectStatus::Spilled).await
+}
+
+async fn upsert(
+    objects: &[&S3Object],
+    compression_job_id: Option<u64>,
+    status: ObjectStatus,
+) -> Result<()> {
+    let pool = super::mysql::get_pool();
+    for chunk in objects.chunks(BULK_CHUNK_SIZE) {
+        let mut builder: QueryBuilder<MySql> = QueryBuilder::new(
+            r"INSERT INTO `ingestor_ingested_objects`
+                (`id`, `bucket`, `key`, `etag`, `size`, `compression_job_id`, `status`) ",
+        );
+        builder.push_values(chunk, |mut row, object| {
+            row.push_bind(get_object_id(object))
+                .push_bind(object.get_bucket())
+                .push_bind(object.get_key())
+                .push_bind(object.get_etag().unwrap_or_default())
+                .push_bind(object.get_size() as u64)
+                .push_bind(compression_job_id)
+                .push_bind(status.as_str());
+        });
+        builder.push(
+            r" ON DUPLICATE KEY UPDATE
+                `compression_job_id` = COALESCE(VALUES(`compression_job_id`), `compression_job_id`),
+                `status` = VALUES(`status`)",
+        );
+        builder.build().execute(&pool).await?;
+    }
+    Ok(())
+}
+
+/// Filters out the objects that are already recorded in the ledger, whatever their status.
+pub async fn filter_unrecorded(objects: Vec<S3Object>) -> Result<Vec<S3Object>> {
+    let pool = super::mysql::get_pool();
+    let mut recorded = HashSet::new();
+    for chunk in objects.chunks(BULK_CHUNK_SIZE) {
+        let mut builder: QueryBuilder<MySql> =
+            QueryBuilder::new(r"SELECT `id` FROM `ingestor_ingested_objects` WHERE `id` IN (");
+        let mut ids = builder.separated(", ");
+        for object in chunk {
+            ids.push_bind(get_object_id(object));
+        }
+        builder.push(")");
+        for row in builder.build().fetch_all(&pool).await? {
+            recorded.insert(row.try_get::<Vec<u8>, _>("id")?);
+        }
+    }
+    Ok(objects
+        .into_iter()
+        .filter(|object| !recorded.contains(&get_object_id(object)))
+        .collect())
+}
+
+/// Updates the status of all objects submitted in the given compression job.
+pub async fn update_status_by_compression_job(
+    compression_job_id: u64,
+    status: ObjectStatus,
+) -> Result<()> {
+    let pool = super::mysql::get_pool();
+    sqlx::query(
+        r"UPDATE `ingestor_ingested_objects` SET `status` = ? WHERE `compression_job_id` = ?",
+    )
+    .bind(status.as_str())
+    .bind(compression_job_id)
+    .execute(&pool)
+    .await?;
+    Ok(())
+}
+
+/// Records that the compression job of the given objects failed, counting the failure against
+/// each object that hasn't been recorded as failed yet if `count_failure` is set.
+pub async fn record_failure(compression_job_id: u64, count_failure: bool) -> Result<()> {
+    let pool = super::mysql::get_pool();
+    sqlx::query(
+        r"UPDATE `ingestor_ingested_objects`
+            SET `status` = ?, `num_failures` = `num_failures` + ?
+            WHERE `compression_job_id` = ? AND `status` = ?",
+    )
+    .bind(ObjectStatus::Failed.as_str())
+    .bind(u32::from(count_failure))
+    .bind(compression_job_id)
+    .bind(ObjectStatus::Submitted.as_str())
+    .execute(&pool)
+    .await?;
+    Ok(())
+}
+
+/// Marks the given objects as quarantined so that they're never ingested again.
+pub async fn quarantine(objects: &[&S3Object]) -> Result<()> {
+    upsert(objects, None, ObjectStatus::Quarantined).await
+}
+
+/// Fetches all ledger entries of the given object, one per distinct etag and size.
+pub async fn fetch(bucket: &str, key: &str) -> Result<Vec<IngestedObject>> {
+    let pool = super::mysql::get_pool();
+    let rows = sqlx::query(
+        r"SELECT `bucket`, `key`, `etag`, `size`, `compression_job_id`, `status`, `num_failures`
+            FROM `ingestor_ingested_objects`
+            WHERE `bucket` = ? AND `key` = ?
+            ORDER BY `update_time`",
+    )
+    .bind(bucket)
+    .bind(key)
+    .fetch_all(&pool)
+    .await?;
+    rows.iter().map(parse_row).collect()
+}
+
+/// Fetches all ledger entries of the objects submitted in the given compression job.
+pub async fn fetch_by_compression_job(compression_job_id: u64) -> Result<Vec<IngestedObject>> {
+    let pool = super::mysql::get_pool();
+    let rows = sqlx::query(
+        r"SELECT `bucket`, `key`, `etag`, `size`, `compression_job_id`, `status`, `num_failures`
+            FROM `ingestor_ingested_objects`
+            WHERE `compression_job_id` = ?
+            ORDER BY `key`",
+    )
+    .bind(compression_job_id)
+    .fetch_all(&pool)
+    .await?;
+    rows.iter().map(parse_row).collect()
+}
+
+/// Fetches the most recently updated ledger entries with the given status.
+pub async fn fetch_by_status(status: ObjectStatus, limit: u32) -> Result<Vec<IngestedObject>> {
+    let pool = super::mysql::get_pool();
+    let rows = sqlx::query(
+        r"SELECT `bucket`, `key`, `etag`, `size`, `compression_job_id`, `status`, `num_failures`
+            FROM `ingestor_ingested_objects`
+            WHERE `status` = ?
+            ORDER BY `update_time` DESC
+            LIMIT ?",
+    )
+    .bind(status.as_str())
+    .bind(limit)
+    .fetch_all(&pool)
+    .await?;
+    rows.iter().map(parse_row).collect()
+}
+
+fn parse_row(row: &sqlx::mysql::MySqlRow) -> Result<IngestedObject> {
+    Ok(IngestedObject {
+        bucket: row.try_get("bucket")?,
+        key: row.try_get("key")?,
+        etag: row.try_get("etag")?,
+        size: row.try_get("size")?,
+        compression_job_id: row.try_get("compression_job_id")?,
+        status: ObjectStatus::parse(row.try_get("status")?)?,
+        num_failures: row.try_get("num_failures")?,
+    })
+}
+
+/// Computes the ledger ID of an object, identifying it by its bucket, key, etag, and size.
+fn get_object_id(object: &S3Object) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    for field in [
+        object.get_bucket().as_bytes(),
+        object.get_key().as_bytes(),
+        object.get_etag().unwrap_or_default().as_bytes(),
+        &object.get_size().to_le_bytes(),
+    ] {
+        hasher.update(field.len().to_le_bytes());
+        hasher.update(field);
+    }
+    hasher.finalize().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_object_id_identity() {
+        let object = S3Object::new(
+            "bucket".into(),
+            "logs/a.log".into(),
+            42,
+            Some("\"0a1b\"".into()),
+        );
+        let same_object = S3Object::new(
+            "bucket".into(),
+            "logs/a.log".into(),
+            42,
+            Some("0a1b".into()),
+        );
+        assert_eq!(get_object_id(&object), get_object_id(&same_object));
+
+        let overwritten = S3Object::new(
+            "bucket".into(),
+            "logs/a.log".into(),
+            42,
+            Some("ffff".into()),
+        );
+        assert_ne!(get_object_id(&object), get_object_id(&overwritten));
+
+        // Field boundaries must not be ambiguous.
+        let shifted = S3Object::new(
+            "bucketlogs/".into(),
+            "a.log".into(),
+            42,
+            Some("0a1b".into()),
+        );
+        assert_ne!(get_object_id(&object), get_object_id(&shifted));
+    }
+}
@@ -1 +1,7 @@
+pub mod checkpoints;
+pub mod compression_jobs;
+pub mod credentials;
+pub mod dead_letters;
+pub mod jobs;
+pub mod ledger;
 pub mod mysql;
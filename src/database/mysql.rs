@@ -1,16 +1,42 @@
+use std::collections::HashSet;
+
 use anyhow::Result;
-use sqlx::mysql::MySqlPool;
+use sqlx::{Row, mysql::MySqlPool};
 use tokio::sync::OnceCell;
 
 static POOL: OnceCell<MySqlPool> = OnceCell::const_new();
 
-pub async fn init(database_url: &str) -> Result<()> {
+/// Connects to the database and sets up the ingestor's tables.
+///
+/// `instance_id` identifies this ingestor among the ingestors sharing the database.
+pub async fn init(database_url: &str, instance_id: &str) -> Result<()> {
     let pool = MySqlPool::connect(database_url).await?;
+    super::jobs::create_table(&pool).await?;
+    super::jobs::migrate_plaintext_secrets(&pool).await?;
+    super::checkpoints::create_table(&pool).await?;
+    super::ledger::create_table(&pool).await?;
+    super::ledger::init(&pool, instance_id).await?;
+    super::compression_jobs::create_table(&pool).await?;
+    super::dead_letters::create_table(&pool).await?;
     POOL.set(pool)
         .map_err(|_| anyhow::anyhow!("Failed to set database pool"))?;
     Ok(())
 }
 
+/// Fetches the names of the columns of the given table, to migrate tables created by earlier
+/// versions.
+pub async fn fetch_columns(pool: &MySqlPool, table: &str) -> Result<HashSet<String>> {
+    let rows = sqlx::query(
+        r"SELECT `COLUMN_NAME` AS `name`
+            FROM `information_schema`.`COLUMNS`
+            WHERE `TABLE_SCHEMA` = DATABASE() AND `TABLE_NAME` = ?",
+    )
+    .bind(table)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(|row| Ok(row.try_get("name")?)).collect()
+}
+
 pub async fn deinit() {
     if let Some(pool) = POOL.get() {
         pool.close().await;
@@ -1,17 +1,35 @@
+mod backfill;
 mod buffering;
 mod compression;
 mod database;
+mod metrics;
 mod scanner;
 mod service;
 mod sqs_listener;
 mod utils;
 
+use std::sync::Arc;
+
 use actix_web::{App, HttpServer, web};
+use buffering::{SpillQueue, SubmissionRetryConfig};
 use clap::Parser;
 use flexi_logger::{Cleanup, Criterion, Duplicate, FileSpec, Logger, Naming};
 use service::{
     ScannerServiceManager,
-    service_method::{create_scanner_job, create_sqs_listener_job, delete_job},
+    service_method::{
+        create_backfill_job,
+        create_scanner_job,
+        create_sqs_listener_job,
+        delete_job,
+        get_backfill_progress,
+        get_compression_job,
+        get_ingested_object,
+        list_compression_jobs,
+        list_dead_letters,
+        list_quarantined_objects,
+        render_metrics,
+        replay_dead_letter,
+    },
 };
 
 #[derive(Parser)]
@@ -19,6 +37,21 @@ struct Args {
     #[clap(long)]
     db_url: String,
 
+    #[clap(
+        long,
+        help = "File holding the hex-encoded 256-bit key used to encrypt the credentials of \
+                persisted jobs. Jobs can only be created and restored with it."
+    )]
+    credential_key_file: Option<std::path::PathBuf>,
+
+    #[clap(
+        long,
+        default_value = "default",
+        help = "ID of this ingestor among the ingestors sharing the database, which must be \
+                unique and stay the same across restarts."
+    )]
+    instance_id: String,
+
     #[clap(
         long,
         help = "Optional S3 endpoint for connecting to S3-compatible storage."
@@ -34,6 +67,35 @@ struct Args {
 
     #[clap(long, default_value_t = 8080, help = "Port to bind the server to.")]
     port: u16,
+
+    #[clap(
+        long,
+        default_value_t = 5,
+        help = "Number of compression job submission attempts before a batch is spilled to disk."
+    )]
+    max_submission_attempts: u32,
+
+    #[clap(
+        long,
+        default_value = ".spill",
+        help = "Directory of the local queue holding batches whose submission kept failing."
+    )]
+    spill_dir: std::path::PathBuf,
+
+    #[clap(
+        long,
+        default_value_t = 3,
+        help = "Number of failed compression jobs an object may be part of before it's \
+                quarantined."
+    )]
+    max_compression_retries: u32,
+
+    #[clap(
+        long,
+        help = "Split the objects of a failed compression job in halves when resubmitting them, \
+                to isolate objects that make compression fail."
+    )]
+    split_failed_batches: bool,
 }
 
 #[actix_web::main]
@@ -63,8 +125,20 @@ async fn main() -> std::io::Result<()> {
 
     let args = Args::parse();
 
+    if let Some(credential_key_file) = &args.credential_key_file {
+        if let Err(e) = database::credentials::init(credential_key_file) {
+            log::error!(
+                "Failed to load credential key from {}: {e}",
+                credential_key_file.display()
+            );
+            return Err(std::io::Error::other("Credential key loading failed."));
+        }
+    } else {
+        log::warn!("No credential key given: jobs can't be created or restored.");
+    }
+
     // Initialize database connection
-    match database::mysql::init(&args.db_url).await {
+    match database::mysql::init(&args.db_url, &args.instance_id).await {
         Ok(()) => log::info!("Database initialized successfully."),
         Err(e) => {
             log::error!("Failed to initialize database: {}. Url: {}", e, args.db_url);
@@ -72,20 +146,54 @@ async fn main() -> std::io::Result<()> {
         }
     }
 
+    compression::tracker::spawn(
+        std::time::Duration::from_secs(10),
+        compression::tracker::ResubmissionConfig {
+            max_retries: args.max_compression_retries,
+            split_batches: args.split_failed_batches,
+        },
+    );
+
+    let spill_queue = Arc::new(SpillQueue::new(args.spill_dir.clone()));
+    spill_queue
+        .clone()
+        .spawn_replayer(std::time::Duration::from_secs(30));
+
     // Initialize service manager
+    #[allow(clippy::duration_suboptimal_units)]
     let scanner_service_manager = web::Data::new(ScannerServiceManager::new(
         100,                                // listener channel size
         std::time::Duration::from_secs(60), // listener channel timeout
         args.s3_endpoint.clone(),           // optional S3 endpoint
         10 * 1024 * 1024,                   // buffer size (bytes)
+        SubmissionRetryConfig {
+            max_attempts: args.max_submission_attempts.max(1),
+            initial_backoff: std::time::Duration::from_secs(1),
+            max_backoff: std::time::Duration::from_mins(1),
+            spill_queue,
+        },
     ));
 
+    if let Err(e) = scanner_service_manager.restore_jobs().await {
+        log::error!("Failed to restore persisted jobs: {e}");
+        return Err(std::io::Error::other("Job restoration failed."));
+    }
+
     HttpServer::new(move || {
         App::new()
             .app_data(scanner_service_manager.clone())
             .service(create_scanner_job)
             .service(create_sqs_listener_job)
+            .service(create_backfill_job)
+            .service(get_backfill_progress)
             .service(delete_job)
+            .service(get_ingested_object)
+            .service(get_compression_job)
+            .service(list_compression_jobs)
+            .service(list_quarantined_objects)
+            .service(render_metrics)
+            .service(list_dead_letters)
+            .service(replay_dead_letter)
     })
     .bind((args.host, args.port))?
     .run()
@@ -0,0 +1,3 @@
+mod registry;
+
+pub use registry::{add, increment, render};
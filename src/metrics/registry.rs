@@ -0,0 +1,78 @@
+use std::{
+    fmt::Write,
+    sync::{
+        LazyLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use dashmap::DashMap;
+
+/// All counters, keyed by their name and labels in the Prometheus exposition format.
+static COUNTERS: LazyLock<DashMap<String, AtomicU64>> = LazyLock::new(DashMap::new);
+
+/// Increments the counter with the given name and labels by one.
+pub fn increment(name: &str, labels: &[(&str, &str)]) {
+    add(name, labels, 1);
+}
+
+/// Increments the counter with the given name and labels by `value`.
+pub fn add(name: &str, labels: &[(&str, &str)], value: u64) {
+    COUNTERS
+        .entry(format_series(name, labels))
+        .or_insert_with(|| AtomicU64::new(0))
+        .fetch_add(value, Ordering::Relaxed);
+}
+
+/// Renders all counters in the Prometheus text exposition format.
+pub fn render() -> String {
+    let mut series: Vec<(String, u64)> = COUNTERS
+        .iter()
+        .map(|entry| (entry.key().clone(), entry.value().load(Ordering::Relaxed)))
+        .collect();
+    series.sort_unstable();
+
+    let mut rendered = String::new();
+    for (name, value) in series {
+        let _ = writeln!(rendered, "{name} {value}");
+    }
+    rendered
+}
+
+fn format_series(name: &str, labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return name.to_string();
+    }
+    let labels = labels
+        .iter()
+        .map(|(label, value)| {
+            format!(
+                "{label}=\"{}\"",
+                value
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("{name}{{{labels}}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_series() {
+        assert_eq!("total", format_series("total", &[]));
+        assert_eq!(
+            r#"total{job_id="1",key="a\"b"}"#,
+            format_series("total", &[("job_id", "1"), ("key", "a\"b")])
+        );
+        assert_eq!(
+            r#"total{key="a\\b\nc"}"#,
+            format_series("total", &[("key", "a\\b\nc")])
+        );
+    }
+}
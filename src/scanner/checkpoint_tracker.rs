@@ -0,0 +1,152 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use dashmap::DashMap;
+
+use super::listing::get_checkpoint_prefix;
+
+/// The keys a scanner job sent for buffering under each key prefix (or partition), tracking how far
+/// their checkpoints may advance.
+///
+/// A job may feed several buffers, one per dataset or for oversized objects, which flush
+/// independently. A checkpoint must not move past a key that's still buffered, even once greater
+/// keys have been durably handled by other buffers, or the buffered key would be skipped after a
+/// restart. Checkpoints therefore only advance up to the lowest key not yet durably handled.
+pub struct CheckpointTracker {
+    key_prefixes: Vec<String>,
+    partition_delimiter: Option<String>,
+    prefixes: DashMap<String, PrefixProgress>,
+}
+
+#[derive(Default)]
+struct PrefixProgress {
+    /// The keys sent for buffering but not durably handled yet, with the number of times each was
+    /// sent.
+    pending: BTreeMap<String, usize>,
+    /// The durably handled keys past the checkpoint.
+    handled: BTreeSet<String>,
+    checkpoint: Option<String>,
+}
+
+impl CheckpointTracker {
+    pub fn new(key_prefixes: Vec<String>, partition_delimiter: Option<String>) -> Self {
+        Self {
+            key_prefixes,
+            partition_delimiter,
+            prefixes: DashMap::new(),
+        }
+    }
+
+    /// Registers a key before its object is sent for buffering.
+    pub fn register(&self, key: &str) {
+        let Some(prefix) = self.get_prefix(key) else {
+            return;
+        };
+        *self
+            .prefixes
+            .entry(prefix.to_string())
+            .or_default()
+            .pending
+            .entry(key.to_string())
+            .or_default() += 1;
+    }
+
+    /// Acknowledges that the object of the given key has been durably handled.
+    ///
+    /// # Returns
+    ///
+    /// The checkpoint prefix of the key, if it's under any of the job's key prefixes.
+    pub fn acknowledge(&self, key: &str) -> Option<String> {
+        let prefix = self.get_prefix(key)?.to_string();
+        self.prefixes
+            .entry(prefix.clone())
+            .or_default()
+            .acknowledge(key);
+        Some(prefix)
+    }
+
+    /// Advances the checkpoint of the given prefix to the greatest handled key below every key
+    /// still pending.
+    ///
+    /// # Returns
+    ///
+    /// The new checkpoint, if it moved.
+    pub fn advance(&self, prefix: &str) -> Option<String> {
+        self.prefixes.get_mut(prefix)?.advance()
+    }
+
+    fn get_prefix<'a>(&self, key: &'a str) -> Option<&'a str> {
+        get_checkpoint_prefix(&self.key_prefixes, self.partition_delimiter.as_deref(), key)
+    }
+}
+
+impl PrefixProgress {
+    fn acknowledge(&mut self, key: &str) {
+        if let Some(num_pending) = self.pending.get_mut(key) {
+            *num_pending -= 1;
+            if *num_pending == 0 {
+                self.pending.remove(key);
+            }
+        }
+        self.handled.insert(key.to_string());
+    }
+
+    fn advance(&mut self) -> Option<String> {
+        let checkpoint = match self.pending.keys().next() {
+            Some(lowest_pending) => self
+                .handled
+                .range::<String, _>(..lowest_pending)
+                .next_back(),
+            None => self.handled.last(),
+        }?
+        .clone();
+        self.handled = self.handled.split_off(&checkpoint);
+        self.handled.remove(&checkpoint);
+        if self.checkpoint.as_ref() >= Some(&checkpoint) {
+            return None;
+        }
+        self.checkpoint = Some(checkpoint.clone());
+        Some(checkpoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_out_of_order_flushes() {
+        let tracker = CheckpointTracker::new(vec!["logs/".to_string()], None);
+        for key in ["logs/a/5", "logs/a/7", "logs/b/9"] {
+            tracker.register(key);
+        }
+
+        // The buffer of dataset `b` flushes first, while the keys of dataset `a` are still
+        // buffered.
+        assert_eq!(Some("logs/".to_string()), tracker.acknowledge("logs/b/9"));
+        assert_eq!(None, tracker.advance("logs/"));
+
+        // The checkpoint catches up as the buffer of dataset `a` flushes.
+        tracker.acknowledge("logs/a/5");
+        assert_eq!(Some("logs/a/5".to_string()), tracker.advance("logs/"));
+        tracker.acknowledge("logs/a/7");
+        assert_eq!(Some("logs/b/9".to_string()), tracker.advance("logs/"));
+        assert_eq!(None, tracker.advance("logs/"));
+
+        assert_eq!(None, tracker.acknowledge("other/1"));
+    }
+
+    #[test]
+    fn test_partitions() {
+        let tracker = CheckpointTracker::new(vec!["logs/".to_string()], Some("/".to_string()));
+        tracker.register("logs/a/1");
+        tracker.register("logs/b/1");
+        tracker.register("logs/b/2");
+
+        // Partitions are checkpointed independently of each other.
+        assert_eq!(Some("logs/b/".to_string()), tracker.acknowledge("logs/b/2"));
+        assert_eq!(None, tracker.advance("logs/b/"));
+        tracker.acknowledge("logs/b/1");
+        assert_eq!(Some("logs/b/2".to_string()), tracker.advance("logs/b/"));
+        assert_eq!(None, tracker.advance("logs/a/"));
+    }
+}
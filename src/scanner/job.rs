@@ -1,29 +1,67 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, btree_map, hash_map::Entry},
+    ops::BitOrAssign,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
 use anyhow::Result;
 use aws_sdk_s3::Client;
-use tokio::{sync::mpsc::Sender, task::JoinHandle, time::sleep};
+use tokio::{
+    sync::{
+        Semaphore,
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+    },
+    task::{JoinHandle, JoinSet},
+    time::{Instant, sleep},
+};
 
-use crate::{scanner::JobParams, utils::S3Object};
+use crate::{
+    buffering::{BufferedObject, HandledObject, ObjectRouter},
+    database::{checkpoints, ledger},
+    metrics,
+    scanner::{
+        JobParams,
+        checkpoint_tracker::CheckpointTracker,
+        listing::{ListingCursor, ObjectLister, list_partitions},
+        reconcile_window::ReconcileWindows,
+    },
+    utils::{KeyFilter, ObjectLimits, S3Object},
+};
 
 pub struct Job {
     id: uuid::Uuid,
     handle: JoinHandle<()>,
+    checkpoint_handle: JoinHandle<()>,
 }
 
 impl Job {
-    pub fn spawn(client: Client, params: JobParams, sender: Sender<S3Object>) -> Self {
+    pub fn spawn(id: uuid::Uuid, client: Client, params: JobParams, router: ObjectRouter) -> Self {
+        let (notifier, handled_objects) = mpsc::unbounded_channel();
+        let checkpoints = Arc::new(CheckpointTracker::new(
+            params
+                .get_key_prefixes()
+                .into_iter()
+                .map(ToString::to_string)
+                .collect(),
+            params.get_partition_delimiter().map(ToString::to_string),
+        ));
+        let checkpoint_handle = tokio::spawn(checkpoint(id, checkpoints.clone(), handled_objects));
         let handle = tokio::spawn(async move {
-            if let Err(e) = execute(client, params, sender).await {
+            if let Err(e) = execute(id, client, params, router, (notifier, checkpoints)).await {
                 log::error!("Job execution failed: {e:?}");
             }
         });
         Self {
-            id: uuid::Uuid::new_v4(),
+            id,
             handle,
+            checkpoint_handle,
         }
     }
 
     pub fn cancel(&self) {
         self.handle.abort();
+        self.checkpoint_handle.abort();
     }
 
     pub const fn get_id(&self) -> uuid::Uuid {
@@ -31,90 +69,701 @@ impl Job {
     }
 }
 
-async fn list_bucket_with_prefix(
-    client: &Client,
-    bucket: &str,
-    prefix: &str,
-    start_after: Option<&str>,
-) -> Result<(Vec<S3Object>, bool)> {
-    let mut scanned_objects = Vec::new();
-
-    let resp_handler = client
-        .list_objects_v2()
-        .bucket(bucket)
-        .prefix(prefix)
-        .set_start_after(start_after.map(std::string::ToString::to_string))
-        .send();
-
-    let resp = match resp_handler.await {
-        Ok(output) => output,
-        Err(e) => {
-            log::error!("Error listing objects in bucket {bucket}: {e:?}");
-            return Err(anyhow::anyhow!(e));
+/// Persists the last key under each key prefix (or partition) up to which every object has been
+/// durably handled by the job's buffers, so that a restarted job resumes from it.
+async fn checkpoint(
+    job_id: uuid::Uuid,
+    tracker: Arc<CheckpointTracker>,
+    mut handled_objects: UnboundedReceiver<HandledObject>,
+) {
+    while let Some(handled) = handled_objects.recv().await {
+        // Coalesce all pending notifications into one update per prefix.
+        let mut updated_prefixes = HashSet::new();
+        let mut next = Some(handled);
+        while let Some(handled) = next.take().or_else(|| handled_objects.try_recv().ok()) {
+            log::debug!(
+                "[{job_id}] Object {} durably handled ({:?}).",
+                handled.object.get_key(),
+                handled.outcome
+            );
+            updated_prefixes.extend(tracker.acknowledge(handled.object.get_key()));
         }
-    };
-
-    if let Some(contents) = resp.contents {
-        for object in contents {
-            if let (Some(key), Some(size)) = (object.key, object.size) {
-                if key.ends_with('/') {
-                    // Skip directory-like entries
-                    log::warn!("Skipping directory-like entry: {key}");
-                    continue;
-                }
-                scanned_objects.push(S3Object::new(
-                    bucket.to_string(),
-                    key,
-                    usize::try_from(size)?,
-                ));
+        for checkpoint_prefix in updated_prefixes {
+            let Some(last_key) = tracker.advance(checkpoint_prefix.as_str()) else {
+                continue;
+            };
+            match checkpoints::upsert(job_id, checkpoint_prefix.as_str(), last_key.as_str()).await {
+                Ok(()) => log::info!("[{job_id}] Checkpointed last ingested key {last_key}."),
+                Err(e) => log::error!("[{job_id}] Failed to checkpoint key {last_key}: {e:?}"),
             }
         }
     }
+}
+
+/// What the listings of a scanner job share.
+struct Scan<L> {
+    id: uuid::Uuid,
+    lister: L,
+    bucket: String,
+    key_filter: KeyFilter,
+    object_limits: ObjectLimits,
+    router: ObjectRouter,
+    notifier: UnboundedSender<HandledObject>,
+    checkpoints: Arc<CheckpointTracker>,
+}
+
+/// The outcome of a listing pass.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct PassOutcome {
+    found_new_objects: bool,
+    /// Whether a listing failed, cutting the pass short.
+    failed: bool,
+}
+
+impl BitOrAssign for PassOutcome {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.found_new_objects |= rhs.found_new_objects;
+        self.failed |= rhs.failed;
+    }
+}
 
-    match resp.is_truncated {
-        Some(is_truncated) => {
-            if is_truncated {
-                log::info!(
-                    "Response is truncated. Followings keys will be handled in the next request."
+impl<L: ObjectLister + Sync> Scan<L> {
+    /// Lists the new objects under a prefix until the end of the listing pass, and sends them to
+    /// the buffer.
+    ///
+    /// A listing error ends the pass early rather than the job: the cursor then restarts from the
+    /// last listed key in the next pass.
+    async fn list_new_objects(
+        &self,
+        prefix: &str,
+        cursor: &mut ListingCursor,
+    ) -> Result<PassOutcome> {
+        let mut outcome = PassOutcome::default();
+        loop {
+            let scan_position = cursor.get_start_after().map(ToString::to_string);
+            let listed_objects = match cursor
+                .next_page(&self.lister, self.bucket.as_str(), prefix)
+                .await
+            {
+                Ok(listed_objects) => listed_objects,
+                Err(e) => {
+                    log::error!(
+                        "[{}] Failed to list objects with prefix {prefix}, retrying in the next \
+                         pass: {e:?}",
+                        self.id
+                    );
+                    outcome.failed = true;
+                    return Ok(outcome);
+                }
+            };
+            let (listed_objects, is_deferred) = self.split_at_deferral(listed_objects);
+            if is_deferred {
+                // The next pass lists the deferred object again.
+                cursor.rewind(
+                    listed_objects
+                        .last()
+                        .map(|object| object.get_key().to_string())
+                        .or(scan_position),
                 );
             }
-            Ok((scanned_objects, is_truncated))
+            outcome.found_new_objects |= self.send_objects(prefix, listed_objects).await?;
+            log::info!("Last listed key: {:?}", cursor.get_start_after());
+            if !cursor.is_mid_pass() {
+                return Ok(outcome);
+            }
         }
-        None => Ok((scanned_objects, false)),
     }
-}
 
-async fn execute(client: Client, params: JobParams, sender: Sender<S3Object>) -> Result<()> {
-    let mut start_after: Option<String> = None;
-    loop {
-        let (scanned_objects, is_truncated) = list_bucket_with_prefix(
-            &client,
-            params.get_bucket(),
-            params.get_key_prefix(),
-            start_after.as_deref(),
-        )
-        .await?;
+    /// Splits the listed objects before the first one that's too recent to be ingested yet, since
+    /// sending the later ones would move the scan position past it.
+    ///
+    /// # Returns
+    ///
+    /// A tuple of:
+    ///
+    /// * The objects before the deferred one.
+    /// * Whether an object was deferred.
+    fn split_at_deferral(&self, mut listed_objects: Vec<S3Object>) -> (Vec<S3Object>, bool) {
+        let Some(idx) = listed_objects.iter().position(|object| {
+            self.key_filter.matches(object.get_key())
+                && self.object_limits.get_deferral(object).is_some()
+        }) else {
+            return (listed_objects, false);
+        };
+        log::info!(
+            "[{}] Deferring object {} and the keys after it until it's old enough.",
+            self.id,
+            listed_objects[idx].get_key()
+        );
+        listed_objects.truncate(idx);
+        (listed_objects, true)
+    }
+
+    /// Sends the objects directly under a partitioned key prefix that are past its top-level scan
+    /// position to the buffer, up to the first deferred one, and advances the scan position.
+    ///
+    /// # Returns
+    ///
+    /// Whether any object was sent.
+    async fn send_top_level_objects(
+        &self,
+        key_prefix: &str,
+        top_level_objects: Vec<S3Object>,
+        top_level_cursor: &mut Option<String>,
+    ) -> Result<bool> {
+        let (new_objects, _) = self.split_at_deferral(
+            top_level_objects
+                .into_iter()
+                .filter(|object| Some(object.get_key()) > top_level_cursor.as_deref())
+                .collect(),
+        );
+        if let Some(last) = new_objects.last() {
+            *top_level_cursor = Some(last.get_key().to_string());
+        }
+        self.send_objects(key_prefix, new_objects).await
+    }
+
+    /// Sends the listed objects that pass the job's filters to the buffer.
+    ///
+    /// # Returns
+    ///
+    /// Whether any object was sent.
+    async fn send_objects(&self, prefix: &str, listed_objects: Vec<S3Object>) -> Result<bool> {
+        let scanned_objects = self
+            .object_limits
+            .retain(self.id, self.key_filter.retain(self.id, listed_objects));
         if scanned_objects.is_empty() {
-            log::info!("No objects found with prefix: {}.", params.get_key_prefix());
-        } else {
-            log::info!(
-                "Found {} objects with prefix: {}",
-                scanned_objects.len(),
-                params.get_key_prefix()
-            );
+            log::info!("No objects found with prefix: {prefix}.");
+            return Ok(false);
         }
+        log::info!(
+            "Found {} objects with prefix: {prefix}",
+            scanned_objects.len()
+        );
         for scanned_object in scanned_objects {
             log::info!("Found file: {scanned_object:?}");
-            start_after = Some(scanned_object.get_key().to_owned());
-            sender.send(scanned_object).await?;
+            self.checkpoints.register(scanned_object.get_key());
+            self.router
+                .send(BufferedObject::new(
+                    scanned_object,
+                    Some(self.notifier.clone()),
+                    None,
+                ))
+                .await?;
+        }
+        Ok(true)
+    }
+}
+
+async fn execute(
+    id: uuid::Uuid,
+    client: Client,
+    params: JobParams,
+    router: ObjectRouter,
+    (notifier, checkpoints): (UnboundedSender<HandledObject>, Arc<CheckpointTracker>),
+) -> Result<()> {
+    let scan = Arc::new(Scan {
+        id,
+        lister: client,
+        bucket: params.get_bucket().to_string(),
+        key_filter: params.get_object_params().get_key_filter()?,
+        object_limits: params.get_object_params().get_object_limits(),
+        router,
+        notifier,
+        checkpoints,
+    });
+    // Each key prefix (or partition) is scanned with its own cursor, resuming from its own
+    // checkpoint.
+    let mut cursors = BTreeMap::new();
+    let mut reconcile_windows = ReconcileWindows::new(params.get_reconcile_lookback());
+    if params.get_partition_delimiter().is_none() {
+        for key_prefix in params.get_key_prefixes() {
+            let cursor = fetch_cursor(id, key_prefix.to_string()).await?;
+            reconcile_windows.start(key_prefix, cursor.get_start_after());
+            cursors.insert(key_prefix.to_string(), cursor);
+        }
+    }
+    // The scan positions among the objects directly under each key prefix in partitioned mode.
+    let mut top_level_cursors = HashMap::new();
+    let mut poll_interval = params.get_poll_interval();
+    let mut next_reconciliation = params
+        .get_reconcile_interval()
+        .map(|interval| Instant::now() + interval);
+    loop {
+        let mut outcome = PassOutcome::default();
+        if let Some(delimiter) = params.get_partition_delimiter() {
+            for key_prefix in params.get_key_prefixes() {
+                // The partitions found so far are still listed if a prefix can't be rediscovered.
+                match discover_partitions(
+                    &scan,
+                    (key_prefix, delimiter),
+                    &mut cursors,
+                    &mut top_level_cursors,
+                    &mut reconcile_windows,
+                )
+                .await
+                {
+                    Ok(found_new_objects) => outcome.found_new_objects |= found_new_objects,
+                    Err(e) => {
+                        log::error!("[{id}] Partition discovery of {key_prefix} failed: {e:?}");
+                        outcome.failed = true;
+                    }
+                }
+            }
+            outcome |= list_partitions_concurrently(
+                &scan,
+                &mut cursors,
+                params.get_listing_concurrency() as usize,
+            )
+            .await?;
+        } else {
+            for (key_prefix, cursor) in &mut cursors {
+                outcome |= scan.list_new_objects(key_prefix, cursor).await?;
+            }
+        }
+
+        if let (Some(due), Some(interval)) = (next_reconciliation, params.get_reconcile_interval())
+            && due <= Instant::now()
+        {
+            for (key_prefix, cursor) in &cursors {
+                let scan_position = cursor.get_start_after();
+                let start_after = reconcile_windows.advance(key_prefix, scan_position);
+                if let Err(e) = reconcile(
+                    id,
+                    &scan.lister,
+                    &params,
+                    (&scan.key_filter, &scan.object_limits),
+                    (key_prefix, start_after.as_deref(), scan_position),
+                    &scan.router,
+                )
+                .await
+                {
+                    log::error!("[{id}] Reconciliation of {key_prefix} failed: {e:?}");
+                }
+            }
+            next_reconciliation = Some(Instant::now() + interval);
+        }
+
+        // Failed listings are retried after backing off like a pass without new objects.
+        poll_interval.update(outcome.found_new_objects && !outcome.failed);
+        log::info!(
+            "[{id}] Next listing pass in {}s.",
+            poll_interval.get().as_secs()
+        );
+        sleep(poll_interval.get()).await;
+    }
+}
+
+/// Creates a cursor resuming the scan of a key prefix (or partition) from its checkpoint.
+async fn fetch_cursor(id: uuid::Uuid, prefix: String) -> Result<ListingCursor> {
+    let cursor = ListingCursor::new(checkpoints::fetch(id, prefix.as_str()).await?);
+    if let Some(key) = cursor.get_start_after() {
+        log::info!("[{id}] Resuming scan of {prefix} after checkpointed key: {key}");
+    }
+    Ok(cursor)
+}
+
+/// Lists the partitions of a key prefix, adding a cursor for each new one, and sends the new
+/// objects directly under the prefix to the buffer.
+///
+/// # Returns
+///
+/// Whether any new object was found directly under the prefix.
+async fn discover_partitions(
+    scan: &Scan<impl ObjectLister + Sync>,
+    (key_prefix, delimiter): (&str, &str),
+    cursors: &mut BTreeMap<String, ListingCursor>,
+    top_level_cursors: &mut HashMap<String, Option<String>>,
+    reconcile_windows: &mut ReconcileWindows,
+) -> Result<bool> {
+    let (partitions, top_level_objects) =
+        list_partitions(&scan.lister, scan.bucket.as_str(), key_prefix, delimiter).await?;
+    for partition in partitions {
+        if let btree_map::Entry::Vacant(entry) = cursors.entry(partition) {
+            log::info!("[{}] Found new partition: {}", scan.id, entry.key());
+            let cursor = fetch_cursor(scan.id, entry.key().clone()).await?;
+            reconcile_windows.start(entry.key(), cursor.get_start_after());
+            entry.insert(cursor);
+        }
+    }
+
+    // Partitions are discovered by listing from the beginning of the prefix, so the objects
+    // directly under it are filtered by their own scan position.
+    let top_level_cursor = match top_level_cursors.entry(key_prefix.to_string()) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => entry.insert(checkpoints::fetch(scan.id, key_prefix).await?),
+    };
+    scan.send_top_level_objects(key_prefix, top_level_objects, top_level_cursor)
+        .await
+}
+
+/// Lists the new objects of every partition, with at most `concurrency` partitions listed at a
+/// time.
+///
+/// A partition failing to be listed doesn't hold back the others: it's retried in the next pass.
+async fn list_partitions_concurrently(
+    scan: &Arc<Scan<impl ObjectLister + Send + Sync + 'static>>,
+    cursors: &mut BTreeMap<String, ListingCursor>,
+    concurrency: usize,
+) -> Result<PassOutcome> {
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+    let mut tasks = JoinSet::new();
+    for (partition, mut cursor) in std::mem::take(cursors) {
+        let permit = semaphore.clone().acquire_owned().await?;
+        let scan = scan.clone();
+        tasks.spawn(async move {
+            let result = scan.list_new_objects(partition.as_str(), &mut cursor).await;
+            drop(permit);
+            (partition, cursor, result)
+        });
+    }
+
+    let mut outcome = PassOutcome::default();
+    while let Some(joined) = tasks.join_next().await {
+        // A partition whose task panicked loses its cursor, and is rediscovered from its
+        // checkpoint.
+        let (partition, cursor, result) = match joined {
+            Ok(joined) => joined,
+            Err(e) => {
+                log::error!("[{}] Partition listing task failed: {e:?}", scan.id);
+                outcome.failed = true;
+                continue;
+            }
+        };
+        match result {
+            Ok(partition_outcome) => outcome |= partition_outcome,
+            Err(e) => {
+                log::error!("[{}] Listing of {partition} failed: {e:?}", scan.id);
+                outcome.failed = true;
+            }
         }
-        log::info!("Last ingested key: {start_after:?}");
-        if is_truncated {
-            // Don't sleep. Restart the next iteration immediately to handle more keys.
-            // TODO:
-            // Use continuation token instead of start_after for better performance and lower cost.
-            continue;
+        cursors.insert(partition, cursor);
+    }
+    Ok(outcome)
+}
+
+/// Relists the keys under a key prefix from `start_after` up to its scan position, and sends the
+/// objects missing from the ingestion ledger to the buffer.
+///
+/// Late objects aren't checkpointed, since their keys precede the scan position. If they're lost
+/// before being durably handled, the next reconciliation passes find them again as long as their
+/// keys are within the relisted window.
+async fn reconcile(
+    id: uuid::Uuid,
+    lister: &(impl ObjectLister + Sync),
+    params: &JobParams,
+    (key_filter, object_limits): (&KeyFilter, &ObjectLimits),
+    (key_prefix, start_after, scan_position): (&str, Option<&str>, Option<&str>),
+    router: &ObjectRouter,
+) -> Result<()> {
+    let Some(scan_position) = scan_position else {
+        return Ok(());
+    };
+    let modified_since = params
+        .get_reconcile_window()
+        .map(|window| {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+            anyhow::Ok(i64::try_from(now.saturating_sub(window).as_secs())?)
+        })
+        .transpose()?;
+
+    let mut cursor = ListingCursor::new(start_after.map(ToString::to_string));
+    let mut num_late_objects = 0;
+    loop {
+        let listed_objects = cursor
+            .next_page(lister, params.get_bucket(), key_prefix)
+            .await?;
+        let candidates = select_late_candidates(
+            listed_objects,
+            scan_position,
+            (key_filter, object_limits),
+            modified_since,
+        );
+        for late_object in ledger::filter_unrecorded(candidates).await? {
+            log::info!("[{id}] Found late object: {late_object:?}");
+            num_late_objects += 1;
+            router
+                .send(BufferedObject::new(late_object, None, None))
+                .await?;
         }
-        sleep(std::time::Duration::from_secs(30)).await;
+        if !cursor.is_mid_pass()
+            || cursor
+                .get_start_after()
+                .is_some_and(|last_key| last_key > scan_position)
+        {
+            break;
+        }
+    }
+
+    log::info!("[{id}] Reconciliation of {key_prefix} found {num_late_objects} late objects.");
+    metrics::add(
+        "scanner_late_objects_total",
+        &[("job_id", id.to_string().as_str())],
+        num_late_objects,
+    );
+    Ok(())
+}
+
+/// Selects the relisted objects that may be late: the objects the job would ingest whose keys
+/// aren't after the scan position, modified since `modified_since` if set.
+fn select_late_candidates(
+    listed_objects: Vec<S3Object>,
+    scan_position: &str,
+    (key_filter, object_limits): (&KeyFilter, &ObjectLimits),
+    modified_since: Option<i64>,
+) -> Vec<S3Object> {
+    listed_objects
+        .into_iter()
+        .filter(|object| object.get_key() <= scan_position)
+        .filter(|object| key_filter.matches(object.get_key()) && object_limits.accepts(object))
+        .filter(|object| {
+            modified_since.is_none_or(|since| {
+                object
+                    .get_last_modified()
+                    .is_none_or(|last_modified| last_modified >= since)
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::sync::mpsc::Receiver;
+
+    use super::*;
+    use crate::{
+        buffering::ListenerMessage,
+        scanner::listing::testing::FakeLister,
+        utils::OversizedObjectPolicy,
+    };
+
+    fn create_scan(
+        lister: FakeLister,
+        object_limits: ObjectLimits,
+    ) -> (Scan<FakeLister>, Receiver<ListenerMessage>) {
+        let (sender, receiver) = mpsc::channel(10);
+        let (notifier, _) = mpsc::unbounded_channel();
+        let scan = Scan {
+            id: uuid::Uuid::nil(),
+            lister,
+            bucket: "bucket".to_string(),
+            key_filter: KeyFilter::default(),
+            object_limits,
+            router: ObjectRouter::new(uuid::Uuid::nil(), sender),
+            notifier,
+            checkpoints: Arc::new(CheckpointTracker::new(vec!["a/".to_string()], None)),
+        };
+        (scan, receiver)
+    }
+
+    fn receive_keys(receiver: &mut Receiver<ListenerMessage>) -> Vec<String> {
+        let mut keys = Vec::new();
+        while let Ok(ListenerMessage::Object(object)) = receiver.try_recv() {
+            keys.push(object.get_object().get_key().to_string());
+        }
+        keys
+    }
+
+    #[tokio::test]
+    async fn test_list_new_objects_retry() -> Result<()> {
+        let lister = FakeLister::new(&["a/1", "a/2", "a/3", "a/4", "a/5"], 2);
+        lister.expire_token("2");
+        let (scan, mut receiver) = create_scan(lister, ObjectLimits::default());
+        let mut cursor = ListingCursor::new(None);
+
+        // An expired continuation token cuts the pass short instead of failing the job.
+        let outcome = scan.list_new_objects("a/", &mut cursor).await?;
+        assert_eq!(
+            PassOutcome {
+                found_new_objects: true,
+                failed: true,
+            },
+            outcome
+        );
+        assert!(!cursor.is_mid_pass());
+        assert_eq!(Some("a/2"), cursor.get_start_after());
+
+        // The next pass restarts after the last listed key.
+        let outcome = scan.list_new_objects("a/", &mut cursor).await?;
+        assert_eq!(
+            PassOutcome {
+                found_new_objects: true,
+                failed: false,
+            },
+            outcome
+        );
+        assert_eq!(
+            vec![
+                (None, None),
+                (None, Some("2".to_string())),
+                (Some("a/2".to_string()), None),
+                (None, Some("4".to_string())),
+            ],
+            scan.lister.take_requests()
+        );
+
+        assert_eq!(
+            vec!["a/1", "a/2", "a/3", "a/4", "a/5"],
+            receive_keys(&mut receiver)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_new_objects_deferral() -> Result<()> {
+        let now = i64::try_from(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())?;
+        let lister = FakeLister::new(&["a/1", "a/2", "a/3", "a/4", "a/5"], 2);
+        lister.set_last_modified("a/3", now);
+        let (scan, mut receiver) = create_scan(
+            lister,
+            ObjectLimits {
+                min_age: Some(Duration::from_hours(1)),
+                ..ObjectLimits::default()
+            },
+        );
+        let mut cursor = ListingCursor::new(None);
+
+        // The pass stops right before the object that's too recent, leaving the keys after it
+        // for later passes.
+        scan.list_new_objects("a/", &mut cursor).await?;
+        assert_eq!(vec!["a/1", "a/2"], receive_keys(&mut receiver));
+        assert!(!cursor.is_mid_pass());
+        assert_eq!(Some("a/2"), cursor.get_start_after());
+        scan.list_new_objects("a/", &mut cursor).await?;
+        assert!(receive_keys(&mut receiver).is_empty());
+        assert_eq!(Some("a/2"), cursor.get_start_after());
+
+        // Once old enough, the object is ingested along with the keys after it.
+        scan.lister.set_last_modified("a/3", now - 3_600);
+        scan.list_new_objects("a/", &mut cursor).await?;
+        assert_eq!(vec!["a/3", "a/4", "a/5"], receive_keys(&mut receiver));
+        assert_eq!(Some("a/5"), cursor.get_start_after());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_routed_oversized_objects() -> Result<()> {
+        let lister = FakeLister::new(&["a/1", "a/2", "a/3"], 10);
+        lister.set_size("a/1", 100);
+        let (mut scan, mut receiver) = create_scan(
+            lister,
+            ObjectLimits {
+                max_size: Some(10),
+                oversized_policy: OversizedObjectPolicy::Route,
+                ..ObjectLimits::default()
+            },
+        );
+        let (oversized_sender, mut oversized_receiver) = mpsc::channel(10);
+        scan.router = scan.router.route_oversized(10, oversized_sender);
+        scan.list_new_objects("a/", &mut ListingCursor::new(None))
+            .await?;
+        assert_eq!(vec!["a/2", "a/3"], receive_keys(&mut receiver));
+        assert_eq!(vec!["a/1"], receive_keys(&mut oversized_receiver));
+
+        // The job's buffer flushes the smaller objects while the oversized one is still buffered
+        // by the buffer of the oversized dataset.
+        scan.checkpoints.acknowledge("a/2");
+        scan.checkpoints.acknowledge("a/3");
+        assert_eq!(None, scan.checkpoints.advance("a/"));
+        scan.checkpoints.acknowledge("a/1");
+        assert_eq!(Some("a/3".to_string()), scan.checkpoints.advance("a/"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_send_top_level_objects() -> Result<()> {
+        let now = i64::try_from(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())?;
+        let lister = FakeLister::new(&["a/1", "a/2", "a/3", "a/4", "a/x/1"], 10);
+        lister.set_last_modified("a/4", now);
+        let (scan, mut receiver) = create_scan(
+            lister,
+            ObjectLimits {
+                min_age: Some(Duration::from_hours(1)),
+                ..ObjectLimits::default()
+            },
+        );
+        let mut top_level_cursor = Some("a/1".to_string());
+
+        // Only the objects past the top-level scan position are sent, up to the deferred one.
+        let (_, top_level_objects) = list_partitions(&scan.lister, "bucket", "a/", "/").await?;
+        assert!(
+            scan.send_top_level_objects("a/", top_level_objects, &mut top_level_cursor)
+                .await?
+        );
+        assert_eq!(vec!["a/2", "a/3"], receive_keys(&mut receiver));
+        assert_eq!(Some("a/3"), top_level_cursor.as_deref());
+
+        scan.lister.set_last_modified("a/4", now - 3_600);
+        let (_, top_level_objects) = list_partitions(&scan.lister, "bucket", "a/", "/").await?;
+        assert!(
+            scan.send_top_level_objects("a/", top_level_objects, &mut top_level_cursor)
+                .await?
+        );
+        assert_eq!(vec!["a/4"], receive_keys(&mut receiver));
+        assert_eq!(Some("a/4"), top_level_cursor.as_deref());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_partitions_concurrently() -> Result<()> {
+        let lister = FakeLister::new(&["a/x/1", "a/x/2", "a/x/3", "a/y/1", "a/y/2"], 2);
+        lister.expire_token("2");
+        let (scan, mut receiver) = create_scan(lister, ObjectLimits::default());
+        let scan = Arc::new(scan);
+        let mut cursors: BTreeMap<String, ListingCursor> = ["a/x/", "a/y/"]
+            .into_iter()
+            .map(|partition| (partition.to_string(), ListingCursor::new(None)))
+            .collect();
+
+        // A partition whose listing fails doesn't hold back the others.
+        let outcome = list_partitions_concurrently(&scan, &mut cursors, 1).await?;
+        assert!(outcome.found_new_objects && outcome.failed);
+        let mut keys = receive_keys(&mut receiver);
+        keys.sort();
+        assert_eq!(vec!["a/x/1", "a/x/2", "a/y/1", "a/y/2"], keys);
+        assert_eq!(Some("a/x/2"), cursors["a/x/"].get_start_after());
+        assert_eq!(Some("a/y/2"), cursors["a/y/"].get_start_after());
+
+        // Partitions that fail to send their objects keep their cursors instead of failing the
+        // job.
+        drop(receiver);
+        cursors.insert("a/x/".to_string(), ListingCursor::new(None));
+        let outcome = list_partitions_concurrently(&scan, &mut cursors, 2).await?;
+        assert!(outcome.failed);
+        assert_eq!(vec!["a/x/", "a/y/"], cursors.keys().collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_late_keys_sorting_lower() -> Result<()> {
+        let now = i64::try_from(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())?;
+        let lister = FakeLister::new(&["a/1", "a/2", "a/5", "a/6"], 10);
+        for key in ["a/1", "a/5"] {
+            lister.set_last_modified(key, now - 7_200);
+        }
+        // `a/2` arrived after the scan moved past it, and `a/6` is yet to be scanned.
+        for key in ["a/2", "a/6"] {
+            lister.set_last_modified(key, now);
+        }
+
+        // Bounded by modification time, reconciliation relists the whole prefix, so late keys
+        // sorting before every previously scanned key are still found.
+        let mut windows = ReconcileWindows::new(None);
+        windows.start("a/", Some("a/5"));
+        let mut cursor = ListingCursor::new(windows.advance("a/", Some("a/5")));
+        let listed_objects = cursor.next_page(&lister, "bucket", "a/").await?;
+        let candidates = select_late_candidates(
+            listed_objects,
+            "a/5",
+            (&KeyFilter::default(), &ObjectLimits::default()),
+            Some(now - 3_600),
+        );
+        assert_eq!(
+            vec!["a/2"],
+            candidates.iter().map(S3Object::get_key).collect::<Vec<_>>()
+        );
+        Ok(())
     }
 }
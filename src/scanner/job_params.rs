@@ -1,12 +1,64 @@
-use serde::Deserialize;
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+use super::poll_interval::PollInterval;
+use crate::utils::{KeyPrefixParams, ObjectParams, deserialize_optional_param, deserialize_param};
+
+/// The default interval between listing passes, in seconds.
+const DEFAULT_POLL_INTERVAL_SECS: u64 = 30;
+
+/// The default bounds of an adaptive interval between listing passes, in seconds.
+const DEFAULT_MIN_POLL_INTERVAL_SECS: u64 = 1;
+const DEFAULT_MAX_POLL_INTERVAL_SECS: u64 = 600;
+
+/// The default and maximum numbers of partitions listed concurrently in partitioned mode.
+const DEFAULT_LISTING_CONCURRENCY: u32 = 8;
+const MAX_LISTING_CONCURRENCY: u32 = 64;
+
+/// The default number of reconciliation intervals whose listed keys are relisted.
+const DEFAULT_RECONCILE_LOOKBACK: u32 = 1;
 
 /// Parameters for a scanner job, specifying the S3 region, bucket, and key prefix.
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct JobParams {
     region: String,
     bucket: String,
-    key_prefix: String,
+    #[serde(flatten)]
+    key_prefix_params: KeyPrefixParams,
     dataset: Option<String>,
+    #[serde(flatten)]
+    object_params: ObjectParams,
+    /// The interval between listing passes, or the initial one if `adaptive_polling` is set.
+    #[serde(default, deserialize_with = "deserialize_optional_param")]
+    poll_interval_secs: Option<u64>,
+    /// Whether to shorten the interval while new objects keep appearing, and lengthen it on empty
+    /// listing passes.
+    #[serde(default, deserialize_with = "deserialize_param")]
+    adaptive_polling: bool,
+    #[serde(default, deserialize_with = "deserialize_optional_param")]
+    min_poll_interval_secs: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_optional_param")]
+    max_poll_interval_secs: Option<u64>,
+    /// The interval between reconciliation passes, which relist the keys before the scan position
+    /// to pick up late-arriving objects. Reconciliation is disabled if unset.
+    #[serde(default, deserialize_with = "deserialize_optional_param")]
+    reconcile_interval_secs: Option<u64>,
+    /// If set, reconciliation passes relist the whole prefix, and only consider objects modified
+    /// within this many seconds.
+    #[serde(default, deserialize_with = "deserialize_optional_param")]
+    reconcile_window_secs: Option<u64>,
+    /// The number of past reconciliation intervals whose listed keys each reconciliation pass
+    /// relists, rather than the whole prefix, unless `reconcile_window_secs` is set.
+    #[serde(default, deserialize_with = "deserialize_optional_param")]
+    reconcile_lookback: Option<u32>,
+    /// If set, each key prefix is split into partitions ending at the first occurrence of this
+    /// delimiter, which are listed concurrently.
+    partition_delimiter: Option<String>,
+    /// The maximum number of partitions listed concurrently.
+    #[serde(default, deserialize_with = "deserialize_optional_param")]
+    listing_concurrency: Option<u32>,
 }
 
 impl JobParams {
@@ -18,11 +70,113 @@ impl JobParams {
         &self.bucket
     }
 
+    pub fn get_key_prefixes(&self) -> Vec<&str> {
+        self.key_prefix_params.get_key_prefixes()
+    }
+
+    /// Returns the prefix shared by all of the job's key prefixes.
     pub fn get_key_prefix(&self) -> &str {
-        &self.key_prefix
+        self.key_prefix_params.get_key_prefix()
     }
 
     pub fn get_dataset(&self) -> Option<&str> {
         self.dataset.as_deref()
     }
+
+    pub const fn get_object_params(&self) -> &ObjectParams {
+        &self.object_params
+    }
+
+    pub fn get_poll_interval(&self) -> PollInterval {
+        let interval = Duration::from_secs(
+            self.poll_interval_secs
+                .unwrap_or(DEFAULT_POLL_INTERVAL_SECS),
+        );
+        if !self.adaptive_polling {
+            return PollInterval::fixed(interval);
+        }
+        let (min, max) = self.get_poll_interval_bounds();
+        PollInterval::adaptive(interval, Duration::from_secs(min), Duration::from_secs(max))
+    }
+
+    pub fn get_reconcile_interval(&self) -> Option<Duration> {
+        self.reconcile_interval_secs.map(Duration::from_secs)
+    }
+
+    pub fn get_reconcile_window(&self) -> Option<Duration> {
+        self.reconcile_window_secs.map(Duration::from_secs)
+    }
+
+    /// Returns the number of past reconciliation intervals whose listed keys are relisted, or
+    /// `None` if reconciliation relists the whole prefix, bounded by modification time instead.
+    pub fn get_reconcile_lookback(&self) -> Option<usize> {
+        if self.reconcile_window_secs.is_some() {
+            return None;
+        }
+        Some(
+            self.reconcile_lookback
+                .unwrap_or(DEFAULT_RECONCILE_LOOKBACK) as usize,
+        )
+    }
+
+    pub fn get_partition_delimiter(&self) -> Option<&str> {
+        self.partition_delimiter.as_deref()
+    }
+
+    pub fn get_listing_concurrency(&self) -> u32 {
+        self.listing_concurrency
+            .unwrap_or(DEFAULT_LISTING_CONCURRENCY)
+    }
+
+    /// Validates that the parameters are consistent with each other.
+    pub fn validate(&self) -> Result<()> {
+        self.key_prefix_params.validate()?;
+        self.object_params.validate()?;
+        let interval = self
+            .poll_interval_secs
+            .unwrap_or(DEFAULT_POLL_INTERVAL_SECS);
+        if interval == 0 {
+            bail!("`poll_interval_secs` must be positive.");
+        }
+        if self.adaptive_polling {
+            let (min, max) = self.get_poll_interval_bounds();
+            if min == 0 || !(min..=max).contains(&interval) {
+                bail!(
+                    "Adaptive polling requires 0 < `min_poll_interval_secs` <= \
+                     `poll_interval_secs` <= `max_poll_interval_secs`."
+                );
+            }
+        }
+        if self.reconcile_interval_secs == Some(0) {
+            bail!("`reconcile_interval_secs` must be positive.");
+        }
+        if self.reconcile_lookback == Some(0) {
+            bail!("`reconcile_lookback` must be positive.");
+        }
+        if self.reconcile_window_secs == Some(0) {
+            bail!("`reconcile_window_secs` must be positive.");
+        }
+        if self.reconcile_lookback.is_some() && self.reconcile_window_secs.is_some() {
+            bail!("`reconcile_lookback` and `reconcile_window_secs` are mutually exclusive.");
+        }
+        if self.partition_delimiter.as_deref() == Some("") {
+            bail!("`partition_delimiter` must not be empty.");
+        }
+        if self.listing_concurrency.is_some() && self.partition_delimiter.is_none() {
+            bail!("`listing_concurrency` requires `partition_delimiter`.");
+        }
+        if !(1..=MAX_LISTING_CONCURRENCY).contains(&self.get_listing_concurrency()) {
+            bail!("`listing_concurrency` must be between 1 and {MAX_LISTING_CONCURRENCY}.");
+        }
+        Ok(())
+    }
+
+    fn get_poll_interval_bounds(&self) -> (u64, u64) {
+        (
+            self.min_poll_interval_secs
+                .unwrap_or(DEFAULT_MIN_POLL_INTERVAL_SECS),
+            self.max_poll_interval_secs
+                .unwrap_or(DEFAULT_MAX_POLL_INTERVAL_SECS),
+        )
+    }
 }
@@ -0,0 +1,443 @@
+use anyhow::Result;
+use aws_sdk_s3::{Client, types::Object};
+
+use crate::utils::S3Object;
+
+/// A page of a bucket listing.
+pub struct ListingPage {
+    pub objects: Vec<S3Object>,
+    /// The sub-prefixes rolled up by the delimiter of the listing, if any.
+    pub common_prefixes: Vec<String>,
+    /// The token to fetch the next page of the same listing with, if the listing is truncated.
+    pub next_continuation_token: Option<String>,
+}
+
+/// A source of paginated bucket listings, in ascending key order.
+pub trait ObjectLister {
+    /// Lists a page of the objects under the given prefix.
+    ///
+    /// A listing starts after `start_after`, and its next pages are fetched with
+    /// `continuation_token` instead. With a `delimiter`, the keys containing it after the prefix
+    /// are rolled up into common prefixes instead of being listed as objects.
+    fn list_page(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        start_after: Option<&str>,
+        continuation_token: Option<&str>,
+        delimiter: Option<&str>,
+    ) -> impl Future<Output = Result<ListingPage>> + Send;
+}
+
+impl ObjectLister for Client {
+    async fn list_page(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        start_after: Option<&str>,
+        continuation_token: Option<&str>,
+        delimiter: Option<&str>,
+    ) -> Result<ListingPage> {
+        let resp = match self
+            .list_objects_v2()
+            .bucket(bucket)
+            .prefix(prefix)
+            .set_start_after(start_after.map(ToString::to_string))
+            .set_continuation_token(continuation_token.map(ToString::to_string))
+            .set_delimiter(delimiter.map(ToString::to_string))
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(e) => {
+                log::error!("Error listing objects in bucket {bucket}: {e:?}");
+                return Err(anyhow::anyhow!(e));
+            }
+        };
+
+        let mut objects = Vec::new();
+        for object in resp.contents.unwrap_or_default() {
+            objects.extend(convert_listed_object(bucket, object)?);
+        }
+        Ok(ListingPage {
+            objects,
+            common_prefixes: resp
+                .common_prefixes
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|common_prefix| common_prefix.prefix)
+                .collect(),
+            next_continuation_token: resp
+                .next_continuation_token
+                .filter(|_| resp.is_truncated.unwrap_or(false)),
+        })
+    }
+}
+
+/// Lists the partitions of a bucket prefix: the sub-prefixes ending at the first `delimiter` after
+/// the prefix.
+///
+/// # Returns
+///
+/// A tuple of:
+///
+/// * The partitions, in ascending order.
+/// * The objects directly under the prefix, which aren't in any partition.
+pub async fn list_partitions(
+    lister: &(impl ObjectLister + Sync),
+    bucket: &str,
+    prefix: &str,
+    delimiter: &str,
+) -> Result<(Vec<String>, Vec<S3Object>)> {
+    let mut partitions = Vec::new();
+    let mut objects = Vec::new();
+    let mut continuation_token = None;
+    loop {
+        let page = lister
+            .list_page(
+                bucket,
+                prefix,
+                None,
+                continuation_token.as_deref(),
+                Some(delimiter),
+            )
+            .await?;
+        partitions.extend(page.common_prefixes);
+        objects.extend(page.objects);
+        continuation_token = page.next_continuation_token;
+        if continuation_token.is_none() {
+            return Ok((partitions, objects));
+        }
+    }
+}
+
+/// Converts an object of a bucket listing, unless it lacks a key or size.
+fn convert_listed_object(bucket: &str, object: Object) -> Result<Option<S3Object>> {
+    let (Some(key), Some(size)) = (object.key, object.size) else {
+        return Ok(None);
+    };
+    Ok(Some(
+        S3Object::new(
+            bucket.to_string(),
+            key,
+            usize::try_from(size)?,
+            object.e_tag,
+        )
+        .with_last_modified(object.last_modified.map(|time| time.secs())),
+    ))
+}
+
+/// Returns the prefix under which the scan position of the given key is checkpointed: the key
+/// prefix it's under, or the partition of that prefix it's in if listings are partitioned by
+/// `delimiter`.
+pub fn get_checkpoint_prefix<'a>(
+    key_prefixes: &[String],
+    delimiter: Option<&str>,
+    key: &'a str,
+) -> Option<&'a str> {
+    let key_prefix = key_prefixes
+        .iter()
+        .find(|key_prefix| key.starts_with(key_prefix.as_str()))?;
+    let Some(delimiter) = delimiter else {
+        return Some(&key[..key_prefix.len()]);
+    };
+    let partition_len = key[key_prefix.len()..]
+        .find(delimiter)
+        .map_or(0, |idx| idx + delimiter.len());
+    Some(&key[..key_prefix.len() + partition_len])
+}
+
+/// The position of a scanner in the listing of a bucket prefix.
+///
+/// Pages of a listing pass are followed with continuation tokens. Once a pass is complete, the next
+/// pass starts after the last listed key, so that only new keys are listed.
+#[derive(Debug, Default)]
+pub struct ListingCursor {
+    start_after: Option<String>,
+    continuation_token: Option<String>,
+}
+
+impl ListingCursor {
+    pub const fn new(start_after: Option<String>) -> Self {
+        Self {
+            start_after,
+            continuation_token: None,
+        }
+    }
+
+    pub fn get_start_after(&self) -> Option<&str> {
+        self.start_after.as_deref()
+    }
+
+    /// Returns whether the current listing pass has more pages.
+    pub const fn is_mid_pass(&self) -> bool {
+        self.continuation_token.is_some()
+    }
+
+    /// Ends the current listing pass, so that the next one starts after the given key instead of
+    /// the last listed one.
+    pub fn rewind(&mut self, start_after: Option<String>) {
+        self.start_after = start_after;
+        self.continuation_token = None;
+    }
+
+    /// Lists the next page of objects, skipping directory-like entries.
+    pub async fn next_page(
+        &mut self,
+        lister: &(impl ObjectLister + Sync),
+        bucket: &str,
+        prefix: &str,
+    ) -> Result<Vec<S3Object>> {
+        let page = match lister
+            .list_page(
+                bucket,
+                prefix,
+                // A continuation token carries the position on its own.
+                self.start_after.as_deref().filter(|_| !self.is_mid_pass()),
+                self.continuation_token.as_deref(),
+                None,
+            )
+            .await
+        {
+            Ok(page) => page,
+            Err(e) => {
+                // The continuation token may have expired, so restart the pass from the last key.
+                self.continuation_token = None;
+                return Err(e);
+            }
+        };
+
+        self.continuation_token = page.next_continuation_token;
+        if let Some(last) = page.objects.last() {
+            self.start_after = Some(last.get_key().to_owned());
+        }
+        Ok(page
+            .objects
+            .into_iter()
+            .filter(|object| {
+                if object.get_key().ends_with('/') {
+                    log::warn!("Skipping directory-like entry: {}", object.get_key());
+                    return false;
+                }
+                true
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+pub mod testing {
+    use std::{
+        collections::{HashMap, HashSet},
+        sync::Mutex,
+    };
+
+    use super::*;
+
+    /// An in-memory bucket listing `page_size` keys per page, recording the requests it serves.
+    pub struct FakeLister {
+        keys: Vec<String>,
+        page_size: usize,
+        requests: Mutex<Vec<(Option<String>, Option<String>)>>,
+        expired_tokens: Mutex<HashSet<String>>,
+        last_modified: Mutex<HashMap<String, i64>>,
+        sizes: Mutex<HashMap<String, usize>>,
+    }
+
+    impl FakeLister {
+        pub fn new(keys: &[&str], page_size: usize) -> Self {
+            let mut keys: Vec<String> = keys.iter().map(ToString::to_string).collect();
+            keys.sort();
+            Self {
+                keys,
+                page_size,
+                requests: Mutex::new(Vec::new()),
+                expired_tokens: Mutex::new(HashSet::new()),
+                last_modified: Mutex::new(HashMap::new()),
+                sizes: Mutex::new(HashMap::new()),
+            }
+        }
+
+        /// Sets the size of the given key, which is 1 byte by default.
+        pub fn set_size(&self, key: &str, size: usize) {
+            self.sizes.lock().unwrap().insert(key.to_string(), size);
+        }
+
+        /// Sets the last modification time of the given key, in seconds since the Unix epoch.
+        pub fn set_last_modified(&self, key: &str, last_modified: i64) {
+            self.last_modified
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), last_modified);
+        }
+
+        /// Fails the requests made with the given continuation token.
+        pub fn expire_token(&self, token: &str) {
+            self.expired_tokens
+                .lock()
+                .unwrap()
+                .insert(token.to_string());
+        }
+
+        pub fn take_requests(&self) -> Vec<(Option<String>, Option<String>)> {
+            std::mem::take(&mut self.requests.lock().unwrap())
+        }
+    }
+
+    impl ObjectLister for FakeLister {
+        async fn list_page(
+            &self,
+            bucket: &str,
+            prefix: &str,
+            start_after: Option<&str>,
+            continuation_token: Option<&str>,
+            delimiter: Option<&str>,
+        ) -> Result<ListingPage> {
+            self.requests.lock().unwrap().push((
+                start_after.map(ToString::to_string),
+                continuation_token.map(ToString::to_string),
+            ));
+            if let Some(token) = continuation_token
+                && self.expired_tokens.lock().unwrap().contains(token)
+            {
+                anyhow::bail!("The continuation token {token} has expired.");
+            }
+            // The listed keys, and the common prefixes each counting as a single entry.
+            let mut entries: Vec<(&str, bool)> = self
+                .keys
+                .iter()
+                .filter_map(|key| key.strip_prefix(prefix).map(|rest| (key, rest)))
+                .map(|(key, rest)| {
+                    delimiter
+                        .and_then(|delimiter| rest.find(delimiter).map(|idx| idx + delimiter.len()))
+                        .map_or((key.as_str(), false), |len| {
+                            (&key[..prefix.len() + len], true)
+                        })
+                })
+                .collect();
+            entries.dedup();
+            let begin = match continuation_token {
+                Some(token) => token.parse()?,
+                None => entries
+                    .iter()
+                    .position(|(entry, _)| Some(*entry) > start_after)
+                    .unwrap_or(entries.len()),
+            };
+            let end = (begin + self.page_size).min(entries.len());
+            let last_modified = self.last_modified.lock().unwrap();
+            let sizes = self.sizes.lock().unwrap();
+            let (common_prefixes, keys): (Vec<_>, Vec<_>) = entries[begin..end]
+                .iter()
+                .partition(|(_, is_common_prefix)| *is_common_prefix);
+            Ok(ListingPage {
+                objects: keys
+                    .into_iter()
+                    .map(|(key, _)| {
+                        let size = sizes.get(key).copied().unwrap_or(1);
+                        S3Object::new(bucket.to_string(), key.to_string(), size, None)
+                            .with_last_modified(last_modified.get(key).copied())
+                    })
+                    .collect(),
+                common_prefixes: common_prefixes
+                    .into_iter()
+                    .map(|(common_prefix, _)| common_prefix.to_string())
+                    .collect(),
+                next_continuation_token: (end < entries.len()).then(|| end.to_string()),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{testing::FakeLister, *};
+
+    fn get_keys(objects: &[S3Object]) -> Vec<&str> {
+        objects.iter().map(S3Object::get_key).collect()
+    }
+
+    #[tokio::test]
+    async fn test_listing_cursor_pagination() {
+        let lister = FakeLister::new(&["a/1", "a/2", "a/3", "a/4", "a/5/"], 2);
+        let mut cursor = ListingCursor::new(None);
+
+        let objects = cursor.next_page(&lister, "bucket", "a/").await.unwrap();
+        assert_eq!(vec!["a/1", "a/2"], get_keys(&objects));
+        assert!(cursor.is_mid_pass());
+        let objects = cursor.next_page(&lister, "bucket", "a/").await.unwrap();
+        assert_eq!(vec!["a/3", "a/4"], get_keys(&objects));
+        assert!(cursor.is_mid_pass());
+        // Directory-like entries are skipped, but still advance the cursor.
+        let objects = cursor.next_page(&lister, "bucket", "a/").await.unwrap();
+        assert!(objects.is_empty());
+        assert!(!cursor.is_mid_pass());
+        assert_eq!(Some("a/5/"), cursor.get_start_after());
+
+        // Pages within a pass are followed with continuation tokens only.
+        assert_eq!(
+            vec![
+                (None, None),
+                (None, Some("2".to_string())),
+                (None, Some("4".to_string())),
+            ],
+            lister.take_requests()
+        );
+
+        // The next pass starts after the last listed key.
+        let objects = cursor.next_page(&lister, "bucket", "a/").await.unwrap();
+        assert!(objects.is_empty());
+        assert_eq!(
+            vec![(Some("a/5/".to_string()), None)],
+            lister.take_requests()
+        );
+    }
+
+    #[test]
+    fn test_checkpoint_prefix() {
+        let key_prefixes = vec!["logs/a/".to_string(), "logs/b/".to_string()];
+        assert_eq!(
+            Some("logs/a/"),
+            get_checkpoint_prefix(&key_prefixes, None, "logs/a/app/1.log")
+        );
+        assert_eq!(
+            Some("logs/a/app/"),
+            get_checkpoint_prefix(&key_prefixes, Some("/"), "logs/a/app/1.log")
+        );
+        assert_eq!(
+            Some("logs/b/"),
+            get_checkpoint_prefix(&key_prefixes, Some("/"), "logs/b/1.log")
+        );
+        assert_eq!(
+            None,
+            get_checkpoint_prefix(&key_prefixes, Some("/"), "logs/c/1.log")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_listing_cursor_resume() {
+        let lister = FakeLister::new(&["a/1", "a/2", "a/3", "b/1"], 10);
+        let mut cursor = ListingCursor::new(Some("a/1".to_string()));
+        let objects = cursor.next_page(&lister, "bucket", "a/").await.unwrap();
+        assert_eq!(vec!["a/2", "a/3"], get_keys(&objects));
+        assert!(!cursor.is_mid_pass());
+        assert_eq!(Some("a/3"), cursor.get_start_after());
+    }
+
+    #[tokio::test]
+    async fn test_list_partitions() {
+        let lister = FakeLister::new(
+            &["a/1", "a/x/1", "a/x/2", "a/y/1", "a/y/z/1", "a/z", "b/1/1"],
+            2,
+        );
+        let (partitions, objects) = list_partitions(&lister, "bucket", "a/", "/").await.unwrap();
+        // Partitions end at the first delimiter after the prefix, and the objects directly under
+        // the prefix are listed on their own.
+        assert_eq!(vec!["a/x/", "a/y/"], partitions);
+        assert_eq!(vec!["a/1", "a/z"], get_keys(&objects));
+        // Listings spanning several pages are followed to their end.
+        assert_eq!(
+            vec![(None, None), (None, Some("2".to_string()))],
+            lister.take_requests()
+        );
+    }
+}
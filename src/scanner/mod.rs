@@ -1,5 +1,10 @@
+mod checkpoint_tracker;
 mod job;
 mod job_params;
+mod listing;
+mod poll_interval;
+mod reconcile_window;
 
 pub use job::Job;
 pub use job_params::JobParams;
+pub use listing::ListingCursor;
@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+/// The interval between the listing passes of a scanner job.
+///
+/// In adaptive mode, the interval is halved after every pass that found new objects, and doubled
+/// after every pass that didn't, within the given bounds.
+#[derive(Debug)]
+pub struct PollInterval {
+    current: Duration,
+    adaptive_bounds: Option<(Duration, Duration)>,
+}
+
+impl PollInterval {
+    pub const fn fixed(interval: Duration) -> Self {
+        Self {
+            current: interval,
+            adaptive_bounds: None,
+        }
+    }
+
+    pub const fn adaptive(initial: Duration, min: Duration, max: Duration) -> Self {
+        Self {
+            current: initial,
+            adaptive_bounds: Some((min, max)),
+        }
+    }
+
+    pub const fn get(&self) -> Duration {
+        self.current
+    }
+
+    /// Adapts the interval to the outcome of the last listing pass.
+    pub fn update(&mut self, found_new_objects: bool) {
+        let Some((min, max)) = self.adaptive_bounds else {
+            return;
+        };
+        self.current = if found_new_objects {
+            (self.current / 2).max(min)
+        } else {
+            self.current.saturating_mul(2).min(max)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adaptive_poll_interval() {
+        let mut interval = PollInterval::adaptive(
+            Duration::from_secs(30),
+            Duration::from_secs(5),
+            Duration::from_mins(2),
+        );
+        interval.update(true);
+        assert_eq!(Duration::from_secs(15), interval.get());
+        interval.update(true);
+        interval.update(true);
+        assert_eq!(Duration::from_secs(5), interval.get());
+        interval.update(false);
+        assert_eq!(Duration::from_secs(10), interval.get());
+        for _ in 0..10 {
+            interval.update(false);
+        }
+        assert_eq!(Duration::from_mins(2), interval.get());
+
+        let mut interval = PollInterval::fixed(Duration::from_secs(30));
+        interval.update(true);
+        interval.update(false);
+        assert_eq!(Duration::from_secs(30), interval.get());
+    }
+}
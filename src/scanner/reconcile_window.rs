@@ -0,0 +1,81 @@
+use std::collections::{HashMap, VecDeque};
+
+/// The keys relisted by the reconciliation passes of each key prefix (or partition).
+///
+/// With a `lookback`, a pass relists the keys after the scan position of the prefix `lookback`
+/// passes earlier, i.e., the keys listed over the last `lookback` reconciliation intervals, rather
+/// than the whole prefix. The first passes after a prefix is resumed start from the position it's
+/// resumed from. Without one, every pass relists the whole prefix.
+#[derive(Debug)]
+pub struct ReconcileWindows {
+    lookback: Option<usize>,
+    /// The scan positions of each prefix at its last passes, oldest first.
+    positions: HashMap<String, VecDeque<Option<String>>>,
+}
+
+impl ReconcileWindows {
+    pub fn new(lookback: Option<usize>) -> Self {
+        Self {
+            lookback,
+            positions: HashMap::new(),
+        }
+    }
+
+    /// Records the scan position a prefix is resumed from.
+    pub fn start(&mut self, prefix: &str, scan_position: Option<&str>) {
+        self.positions.insert(
+            prefix.to_string(),
+            VecDeque::from([scan_position.map(ToString::to_string)]),
+        );
+    }
+
+    /// Starts a reconciliation pass of a prefix at the given scan position.
+    ///
+    /// # Returns
+    ///
+    /// The key after which the pass relists the prefix.
+    pub fn advance(&mut self, prefix: &str, scan_position: Option<&str>) -> Option<String> {
+        let lookback = self.lookback?;
+        let positions = self
+            .positions
+            .entry(prefix.to_string())
+            .or_insert_with(|| VecDeque::from([None]));
+        let start_after = positions.front().cloned().flatten();
+        positions.push_back(scan_position.map(ToString::to_string));
+        while positions.len() > lookback {
+            positions.pop_front();
+        }
+        start_after
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconcile_windows() {
+        let mut windows = ReconcileWindows::new(Some(2));
+        windows.start("a/", Some("a/1"));
+        assert_eq!(Some("a/1".to_string()), windows.advance("a/", Some("a/3")));
+        assert_eq!(Some("a/1".to_string()), windows.advance("a/", Some("a/5")));
+        assert_eq!(Some("a/3".to_string()), windows.advance("a/", Some("a/8")));
+        assert_eq!(Some("a/5".to_string()), windows.advance("a/", Some("a/8")));
+
+        // A prefix that was never resumed is relisted from its beginning.
+        assert_eq!(None, windows.advance("b/", Some("b/2")));
+        assert_eq!(None, windows.advance("b/", Some("b/4")));
+        assert_eq!(Some("b/2".to_string()), windows.advance("b/", Some("b/6")));
+
+        let mut windows = ReconcileWindows::new(Some(1));
+        windows.start("a/", None);
+        assert_eq!(None, windows.advance("a/", Some("a/3")));
+        assert_eq!(Some("a/3".to_string()), windows.advance("a/", Some("a/5")));
+
+        // Without a lookback, the whole prefix is relisted.
+        let mut windows = ReconcileWindows::new(None);
+        windows.start("a/", Some("a/1"));
+        assert_eq!(None, windows.advance("a/", Some("a/3")));
+        assert_eq!(None, windows.advance("a/", Some("a/5")));
+    }
+}
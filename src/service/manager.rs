@@ -1,30 +1,38 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use actix_web_httpauth::extractors::basic::BasicAuth;
-use anyhow::{Result, anyhow};
+use anyhow::{Result, anyhow, bail};
 use dashmap::DashMap;
 use secrecy::{ExposeSecret, SecretString};
 use uuid::Uuid;
 
 use crate::{
-    buffering::{Listener, ListenerKey},
+    backfill::{Job as BackfillJob, JobParams as BackfillJobParams, ProgressReport},
+    buffering::{ListenerKey, ListenerRegistry, ObjectRouter, SubmissionRetryConfig},
+    database::{
+        credentials,
+        dead_letters,
+        jobs::{self, JobRecord, JobStatus, JobType},
+    },
     scanner::{Job as ScannerJob, JobParams as ScannerJobParams},
-    sqs_listener::{Job as SqsListenerJob, JobParams as SqsListenerJobParams},
-    utils::{create_s3_client, create_sqs_client},
+    sqs_listener::{
+        DEFAULT_MESSAGE_GROUP_ID,
+        Job as SqsListenerJob,
+        JobParams as SqsListenerJobParams,
+    },
+    utils::{ObjectParams, OversizedObjectPolicy, create_s3_client, create_sqs_client},
 };
 
 enum Job {
     Scanner(ScannerJob),
     SqsListener(SqsListenerJob),
+    Backfill(BackfillJob),
 }
 
 pub struct ScannerServiceManager {
     job_table: DashMap<Uuid, Job>,
-    listener_table: DashMap<ListenerKey, Listener>,
-    listener_channel_size: usize,
-    listener_channel_timeout: Duration,
+    listeners: Arc<ListenerRegistry>,
     s3_endpoint: Option<String>,
-    buffer_size: usize,
 }
 
 impl Job {
@@ -32,6 +40,7 @@ impl Job {
         match self {
             Self::Scanner(job) => job.cancel(),
             Self::SqsListener(job) => job.cancel(),
+            Self::Backfill(job) => job.cancel(),
         }
     }
 }
@@ -42,22 +51,182 @@ impl ScannerServiceManager {
         listener_channel_timeout: Duration,
         s3_endpoint: Option<String>,
         buffer_size: usize,
+        retry_config: SubmissionRetryConfig,
     ) -> Self {
         Self {
             job_table: DashMap::new(),
-            listener_table: DashMap::new(),
-            listener_channel_size,
-            listener_channel_timeout,
+            listeners: Arc::new(ListenerRegistry::new(
+                listener_channel_size,
+                listener_channel_timeout,
+                buffer_size,
+                retry_config,
+            )),
             s3_endpoint,
-            buffer_size,
         }
     }
 
-    pub async fn create_scanner_job(&self, auth: &BasicAuth, job_params: ScannerJobParams) -> Uuid {
+    pub async fn create_scanner_job(
+        &self,
+        auth: &BasicAuth,
+        job_params: ScannerJobParams,
+    ) -> Result<Uuid> {
         log::info!("Received scanner job creation request {job_params:?}.");
-        let access_key_id = auth.user_id().to_owned();
-        let secret_access_key = SecretString::from(auth.password().unwrap_or("").to_owned());
+        let (access_key_id, secret_access_key) = get_credentials(auth);
+
+        let record = JobRecord {
+            id: Uuid::new_v4(),
+            job_type: JobType::Scanner,
+            params: serde_json::to_string(&job_params)?,
+            access_key_id: access_key_id.clone(),
+            secret_access_key: secret_access_key.clone(),
+        };
+        check_credential_key()?;
+        self.spawn_scanner_job(record.id, access_key_id, &secret_access_key, job_params)
+            .await?;
+        self.persist_spawned_job(&record).await?;
+        Ok(record.id)
+    }
+
+    pub async fn create_sqs_listener_job(
+        &self,
+        auth: &BasicAuth,
+        job_params: SqsListenerJobParams,
+    ) -> Result<Uuid> {
+        log::info!(
+            "Received SQS listener job creation request {job_params:?}. SQS URL: {}",
+            job_params.get_sqs_url()
+        );
+        let (access_key_id, secret_access_key) = get_credentials(auth);
+
+        let record = JobRecord {
+            id: Uuid::new_v4(),
+            job_type: JobType::SqsListener,
+            params: serde_json::to_string(&job_params)?,
+            access_key_id: access_key_id.clone(),
+            secret_access_key: secret_access_key.clone(),
+        };
+        check_credential_key()?;
+        self.spawn_sqs_listener_job(record.id, access_key_id, &secret_access_key, job_params)
+            .await?;
+        self.persist_spawned_job(&record).await?;
+        Ok(record.id)
+    }
+
+    pub async fn create_backfill_job(
+        &self,
+        auth: &BasicAuth,
+        job_params: BackfillJobParams,
+    ) -> Result<Uuid> {
+        log::info!("Received backfill job creation request {job_params:?}.");
+        let (access_key_id, secret_access_key) = get_credentials(auth);
+
+        let record = JobRecord {
+            id: Uuid::new_v4(),
+            job_type: JobType::Backfill,
+            params: serde_json::to_string(&job_params)?,
+            access_key_id: access_key_id.clone(),
+            secret_access_key: secret_access_key.clone(),
+        };
+        check_credential_key()?;
+        self.spawn_backfill_job(record.id, access_key_id, &secret_access_key, job_params)
+            .await?;
+        self.persist_spawned_job(&record).await?;
+        Ok(record.id)
+    }
+
+    /// Persists a newly spawned job, stopping it if it can't be persisted.
+    ///
+    /// Jobs are spawned before being persisted, so that a job that fails to spawn is never restored
+    /// on the next restart, only to fail again.
+    async fn persist_spawned_job(&self, record: &JobRecord) -> Result<()> {
+        if let Err(e) = jobs::insert(record).await {
+            if let Some((_, job)) = self.job_table.remove(&record.id) {
+                job.cancel();
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Returns the progress of the given backfill job.
+    pub fn get_backfill_progress(&self, job_id: &str) -> Result<ProgressReport> {
+        let id =
+            Uuid::parse_str(job_id).map_err(|_| anyhow!("Invalid job_id format: {job_id}."))?;
+        match self.job_table.get(&id).as_deref() {
+            Some(Job::Backfill(job)) => Ok(job.get_progress()),
+            Some(_) => Err(anyhow!("Job {job_id} is not a backfill job.")),
+            None => Err(anyhow!("Job {job_id} not found.")),
+        }
+    }
+
+    /// Re-spawns every active job persisted in the database with its original ID.
+    ///
+    /// Jobs whose persisted parameters can't be deserialized, or whose secret can't be decrypted,
+    /// are logged and skipped.
+    pub async fn restore_jobs(&self) -> Result<()> {
+        for record in jobs::fetch_active().await? {
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    log::error!("Failed to restore job: {e}. Skipping.");
+                    continue;
+                }
+            };
+            let id = record.id;
+            let job_type = record.job_type;
+            match self.restore_job(record).await {
+                Ok(()) => log::info!("Restored {} job {id}.", job_type.as_str()),
+                Err(e) => log::error!(
+                    "Failed to restore {} job {id}: {e}. Skipping.",
+                    job_type.as_str()
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    async fn restore_job(&self, record: JobRecord) -> Result<()> {
+        match record.job_type {
+            JobType::Scanner => {
+                self.spawn_scanner_job(
+                    record.id,
+                    record.access_key_id,
+                    &record.secret_access_key,
+                    serde_json::from_str(record.params.as_str())?,
+                )
+                .await?;
+            }
+            JobType::SqsListener => {
+                self.spawn_sqs_listener_job(
+                    record.id,
+                    record.access_key_id,
+                    &record.secret_access_key,
+                    serde_json::from_str(record.params.as_str())?,
+                )
+                .await?;
+            }
+            JobType::Backfill => {
+                // Backfill jobs restart from the beginning of their range; objects that were
+                // already ingested are skipped through the ledger.
+                self.spawn_backfill_job(
+                    record.id,
+                    record.access_key_id,
+                    &record.secret_access_key,
+                    serde_json::from_str(record.params.as_str())?,
+                )
+                .await?;
+            }
+        }
+        Ok(())
+    }
 
+    async fn spawn_scanner_job(
+        &self,
+        id: Uuid,
+        access_key_id: String,
+        secret_access_key: &SecretString,
+        job_params: ScannerJobParams,
+    ) -> Result<()> {
         let listener_key = ListenerKey::new(
             job_params
                 .get_dataset()
@@ -69,53 +238,49 @@ impl ScannerServiceManager {
             secret_access_key.expose_secret().clone(),
         );
 
-        let s3_endpoint = self.s3_endpoint.as_ref().map_or_else(
-            || format!("https://s3.{}.amazonaws.com", job_params.get_region()),
-            ToString::to_string,
-        );
+        let client = self
+            .create_s3_client(job_params.get_region(), &access_key_id, secret_access_key)
+            .await;
+        let router = self.get_object_router(id, listener_key, job_params.get_object_params())?;
+        let job = ScannerJob::spawn(id, client, job_params, router);
+        self.job_table.insert(job.get_id(), Job::Scanner(job));
+        Ok(())
+    }
 
-        let client = create_s3_client(
-            s3_endpoint.as_str(),
-            job_params.get_region(),
-            &access_key_id,
-            &secret_access_key,
-        )
-        .await;
-        let job = ScannerJob::spawn(
-            client,
-            job_params,
-            self.listener_table
-                .entry(listener_key.clone())
-                .or_insert_with(|| {
-                    log::info!("Creating a new listener.");
-                    Listener::spawn(
-                        listener_key,
-                        self.listener_channel_timeout,
-                        self.listener_channel_size,
-                        self.buffer_size,
-                    )
-                })
-                .get_new_sender(),
+    async fn spawn_backfill_job(
+        &self,
+        id: Uuid,
+        access_key_id: String,
+        secret_access_key: &SecretString,
+        job_params: BackfillJobParams,
+    ) -> Result<()> {
+        let listener_key = ListenerKey::new(
+            job_params
+                .get_dataset()
+                .map(std::string::ToString::to_string),
+            job_params.get_bucket().to_string(),
+            job_params.get_key_prefix().to_string(),
+            job_params.get_region().to_string(),
+            access_key_id.clone(),
+            secret_access_key.expose_secret().clone(),
         );
 
-        let id = job.get_id();
-        self.job_table.insert(id, Job::Scanner(job));
-        id
+        let client = self
+            .create_s3_client(job_params.get_region(), &access_key_id, secret_access_key)
+            .await;
+        let router = self.get_object_router(id, listener_key, job_params.get_object_params())?;
+        let job = BackfillJob::spawn(id, client, job_params, router);
+        self.job_table.insert(job.get_id(), Job::Backfill(job));
+        Ok(())
     }
 
-    pub async fn create_sqs_listener_job(
+    async fn spawn_sqs_listener_job(
         &self,
-        auth: &BasicAuth,
+        id: Uuid,
+        access_key_id: String,
+        secret_access_key: &SecretString,
         job_params: SqsListenerJobParams,
-    ) -> Uuid {
-        log::info!(
-            "Received SQS listener job creation request {job_params:?}. SQS URL: {}",
-            job_params.get_sqs_url()
-        );
-
-        let access_key_id = auth.user_id().to_owned();
-        let secret_access_key = SecretString::from(auth.password().unwrap_or("").to_owned());
-
+    ) -> Result<()> {
         let listener_key = ListenerKey::new(
             job_params
                 .get_dataset()
@@ -128,30 +293,116 @@ impl ScannerServiceManager {
         );
 
         let client =
-            create_sqs_client(job_params.get_region(), &access_key_id, &secret_access_key).await;
-        let job = SqsListenerJob::spawn(
-            client,
-            job_params,
-            self.listener_table
-                .entry(listener_key.clone())
-                .or_insert_with(|| {
-                    log::info!("Creating a new listener.");
-                    Listener::spawn(
-                        listener_key,
-                        self.listener_channel_timeout,
-                        self.listener_channel_size,
-                        self.buffer_size,
-                    )
-                })
-                .get_new_sender(),
+            create_sqs_client(job_params.get_region(), &access_key_id, secret_access_key).await;
+        let router = self.get_object_router(id, listener_key, job_params.get_object_params())?;
+        let job = SqsListenerJob::spawn(id, client, job_params, router);
+        self.job_table.insert(job.get_id(), Job::SqsListener(job));
+        Ok(())
+    }
+
+    async fn create_s3_client(
+        &self,
+        region: &str,
+        access_key_id: &str,
+        secret_access_key: &SecretString,
+    ) -> aws_sdk_s3::Client {
+        let s3_endpoint = self.s3_endpoint.as_ref().map_or_else(
+            || format!("https://s3.{region}.amazonaws.com"),
+            ToString::to_string,
         );
+        create_s3_client(
+            s3_endpoint.as_str(),
+            region,
+            access_key_id,
+            secret_access_key,
+        )
+        .await
+    }
 
-        let id = job.get_id();
-        self.job_table.insert(id, Job::SqsListener(job));
-        id
+    /// Returns the router sending the objects of job `id` to the listener with the given key, to
+    /// the listeners of the datasets rendered from the dataset template if any, or to the
+    /// listener of the oversized dataset for oversized objects under the `route` policy.
+    fn get_object_router(
+        &self,
+        id: Uuid,
+        listener_key: ListenerKey,
+        object_params: &ObjectParams,
+    ) -> Result<ObjectRouter> {
+        let oversized_listener_key = listener_key.with_dataset(
+            object_params
+                .get_oversized_dataset()
+                .map(ToString::to_string),
+        );
+        let mut router = ObjectRouter::new(id, self.listeners.get_sender(listener_key.clone()));
+        if let Some(dataset_template) = object_params.get_dataset_template()? {
+            router = router.route_by_dataset_template(
+                dataset_template,
+                listener_key,
+                self.listeners.clone(),
+            );
+        }
+        let object_limits = object_params.get_object_limits();
+        let Some(max_object_size) = object_limits.max_size else {
+            return Ok(router);
+        };
+        Ok(match object_limits.oversized_policy {
+            OversizedObjectPolicy::Skip => router,
+            OversizedObjectPolicy::Isolate => router.isolate_oversized(max_object_size),
+            OversizedObjectPolicy::Route => router.route_oversized(
+                max_object_size,
+                self.listeners.get_sender(oversized_listener_key),
+            ),
+        })
+    }
+
+    /// Sends a dead-lettered SQS message back to the queue of the job it was received by, and
+    /// removes it from the dead-letter table.
+    ///
+    /// Only the credentials the job was created with may replay its dead letters.
+    pub async fn replay_dead_letter(&self, auth: &BasicAuth, id: u64) -> Result<()> {
+        let dead_letter = dead_letters::fetch_one(id)
+            .await?
+            .ok_or_else(|| anyhow!("Dead letter {id} not found."))?;
+        let job_id = Uuid::parse_str(dead_letter.job_id.as_str())?;
+        let record = jobs::fetch_one(job_id)
+            .await?
+            .ok_or_else(|| anyhow!("Job {job_id} not found."))?;
+        if record.job_type != JobType::SqsListener {
+            bail!("Job {job_id} is not an SQS listener job.");
+        }
+        let (access_key_id, secret_access_key) = get_credentials(auth);
+        if access_key_id != record.access_key_id
+            || secret_access_key.expose_secret() != record.secret_access_key.expose_secret()
+        {
+            bail!("Dead letter {id} belongs to a job created with other credentials.");
+        }
+        let job_params: SqsListenerJobParams = serde_json::from_str(record.params.as_str())?;
+
+        let client = create_sqs_client(
+            job_params.get_region(),
+            &record.access_key_id,
+            &record.secret_access_key,
+        )
+        .await;
+        let mut request = client
+            .send_message()
+            .queue_url(job_params.get_sqs_url())
+            .message_body(dead_letter.body);
+        if job_params.is_fifo() {
+            request = request
+                .message_group_id(
+                    dead_letter
+                        .message_group_id
+                        .unwrap_or_else(|| DEFAULT_MESSAGE_GROUP_ID.to_string()),
+                )
+                .message_deduplication_id(format!("dead-letter-{id}"));
+        }
+        request.send().await?;
+        dead_letters::delete(id).await?;
+        log::info!("Replayed dead letter {id} to the SQS queue of job {job_id}.");
+        Ok(())
     }
 
-    #[allow(clippy::unused_async)]
     pub async fn delete_job(&self, job_id: &str) -> Result<()> {
         let Ok(id) = Uuid::parse_str(job_id.to_string().as_str()) else {
             let error_msg = format!("Invalid job_id format: {job_id}.");
@@ -159,14 +410,34 @@ impl ScannerServiceManager {
             return Err(anyhow!(error_msg));
         };
 
-        if let Some((_, job)) = self.job_table.remove(&id) {
-            job.cancel();
-            log::info!("Job {job_id} cancelled and removed.");
-            Ok(())
-        } else {
+        if !self.job_table.contains_key(&id) {
             let error_msg = format!("Job {job_id} not found for deletion.");
             log::warn!("{}", error_msg.as_str());
-            Err(anyhow!(error_msg))
+            return Err(anyhow!(error_msg));
+        }
+
+        // Persist the cancellation first, so that a job that keeps running is never cancelled
+        // on the next restart, and a cancelled one is never restored.
+        jobs::update_status(id, JobStatus::Cancelled).await?;
+        if let Some((_, job)) = self.job_table.remove(&id) {
+            job.cancel();
         }
+        log::info!("Job {job_id} cancelled and removed.");
+        Ok(())
     }
 }
+
+/// Rejects the creation of a job if its credentials can't be encrypted for persistence.
+fn check_credential_key() -> Result<()> {
+    if !credentials::is_initialized() {
+        bail!("Jobs can't be created without a credential key. Set --credential-key-file.");
+    }
+    Ok(())
+}
+
+fn get_credentials(auth: &BasicAuth) -> (String, SecretString) {
+    (
+        auth.user_id().to_owned(),
+        SecretString::from(auth.password().unwrap_or("").to_owned()),
+    )
+}
@@ -1,8 +1,16 @@
-use actix_web::{HttpResponse, Responder, get, web};
+use actix_web::{HttpResponse, Responder, get, post, web};
 use actix_web_httpauth::extractors::basic::BasicAuth;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use super::manager::ScannerServiceManager;
+use crate::{
+    compression::CompressionJobStatus,
+    database::{
+        compression_jobs::{self, TrackedCompressionJob},
+        dead_letters,
+        ledger::{self, IngestedObject, ObjectStatus},
+    },
+};
 
 #[get("/scanner/create")]
 pub async fn create_scanner_job(
@@ -10,10 +18,16 @@ pub async fn create_scanner_job(
     auth: BasicAuth,
     query: web::Query<crate::scanner::JobParams>,
 ) -> impl Responder {
-    let job_id = service_mgr
+    if let Err(e) = query.validate() {
+        return HttpResponse::BadRequest().body(format!("Error: {e}"));
+    }
+    match service_mgr
         .create_scanner_job(&auth, query.into_inner())
-        .await;
-    HttpResponse::Ok().body(job_id.to_string())
+        .await
+    {
+        Ok(job_id) => HttpResponse::Ok().body(job_id.to_string()),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Error: {e}")),
+    }
 }
 
 #[get("/sqs_listener/create")]
@@ -22,10 +36,34 @@ pub async fn create_sqs_listener_job(
     auth: BasicAuth,
     query: web::Query<crate::sqs_listener::JobParams>,
 ) -> impl Responder {
-    let job_id = service_mgr
+    if let Err(e) = query.validate() {
+        return HttpResponse::BadRequest().body(format!("Error: {e}"));
+    }
+    match service_mgr
         .create_sqs_listener_job(&auth, query.into_inner())
-        .await;
-    HttpResponse::Ok().body(job_id.to_string())
+        .await
+    {
+        Ok(job_id) => HttpResponse::Ok().body(job_id.to_string()),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Error: {e}")),
+    }
+}
+
+#[get("/backfill/create")]
+pub async fn create_backfill_job(
+    service_mgr: web::Data<ScannerServiceManager>,
+    auth: BasicAuth,
+    query: web::Query<crate::backfill::JobParams>,
+) -> impl Responder {
+    if let Err(e) = query.validate() {
+        return HttpResponse::BadRequest().body(format!("Error: {e}"));
+    }
+    match service_mgr
+        .create_backfill_job(&auth, query.into_inner())
+        .await
+    {
+        Ok(job_id) => HttpResponse::Ok().body(job_id.to_string()),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Error: {e}")),
+    }
 }
 
 #[derive(Deserialize)]
@@ -43,3 +81,146 @@ pub async fn delete_job(
         Err(e) => HttpResponse::BadRequest().body(format!("Error: {e}")),
     }
 }
+
+#[get("/backfill/progress")]
+pub async fn get_backfill_progress(
+    service_mgr: web::Data<ScannerServiceManager>,
+    query: web::Query<JobIdQuery>,
+) -> impl Responder {
+    match service_mgr.get_backfill_progress(query.job_id.as_str()) {
+        Ok(progress) => HttpResponse::Ok().json(progress),
+        Err(e) => HttpResponse::BadRequest().body(format!("Error: {e}")),
+    }
+}
+
+#[derive(Deserialize)]
+struct ObjectQuery {
+    bucket: String,
+    key: String,
+}
+
+#[get("/object")]
+pub async fn get_ingested_object(
+    _auth: BasicAuth,
+    query: web::Query<ObjectQuery>,
+) -> impl Responder {
+    match ledger::fetch(query.bucket.as_str(), query.key.as_str()).await {
+        Ok(objects) => HttpResponse::Ok().json(objects),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Error: {e}")),
+    }
+}
+
+#[derive(Deserialize)]
+struct CompressionJobQuery {
+    job_id: u64,
+}
+
+#[derive(Serialize)]
+struct CompressionJobResponse {
+    #[serde(flatten)]
+    job: TrackedCompressionJob,
+    objects: Vec<IngestedObject>,
+}
+
+#[get("/compression_job")]
+pub async fn get_compression_job(
+    _auth: BasicAuth,
+    query: web::Query<CompressionJobQuery>,
+) -> impl Responder {
+    let job = match compression_jobs::fetch_one(query.job_id).await {
+        Ok(Some(job)) => job,
+        Ok(None) => {
+            return HttpResponse::NotFound()
+                .body(format!("Compression job {} not found.", query.job_id));
+        }
+        Err(e) => return HttpResponse::InternalServerError().body(format!("Error: {e}")),
+    };
+    match ledger::fetch_by_compression_job(query.job_id).await {
+        Ok(objects) => HttpResponse::Ok().json(CompressionJobResponse { job, objects }),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Error: {e}")),
+    }
+}
+
+#[derive(Deserialize)]
+struct CompressionJobsQuery {
+    status: Option<String>,
+    limit: Option<u32>,
+}
+
+#[get("/compression_jobs")]
+pub async fn list_compression_jobs(
+    _auth: BasicAuth,
+    query: web::Query<CompressionJobsQuery>,
+) -> impl Responder {
+    let status = match query.status.as_deref().map(CompressionJobStatus::parse) {
+        Some(Ok(status)) => Some(status),
+        Some(Err(e)) => return HttpResponse::BadRequest().body(format!("Error: {e}")),
+        None => None,
+    };
+    match compression_jobs::fetch(status, query.limit.unwrap_or(100)).await {
+        Ok(jobs) => HttpResponse::Ok().json(jobs),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Error: {e}")),
+    }
+}
+
+#[get("/metrics")]
+pub async fn render_metrics(_auth: BasicAuth) -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(crate::metrics::render())
+}
+
+#[derive(Deserialize)]
+struct LimitQuery {
+    limit: Option<u32>,
+}
+
+#[get("/quarantined_objects")]
+pub async fn list_quarantined_objects(
+    _auth: BasicAuth,
+    query: web::Query<LimitQuery>,
+) -> impl Responder {
+    match ledger::fetch_by_status(ObjectStatus::Quarantined, query.limit.unwrap_or(100)).await {
+        Ok(objects) => HttpResponse::Ok().json(objects),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Error: {e}")),
+    }
+}
+
+#[derive(Deserialize)]
+struct DeadLettersQuery {
+    job_id: Option<String>,
+    limit: Option<u32>,
+}
+
+#[get("/dead_letters")]
+pub async fn list_dead_letters(
+    _auth: BasicAuth,
+    query: web::Query<DeadLettersQuery>,
+) -> impl Responder {
+    let job_id = match query.job_id.as_deref().map(uuid::Uuid::parse_str) {
+        Some(Ok(job_id)) => Some(job_id),
+        Some(Err(e)) => return HttpResponse::BadRequest().body(format!("Error: {e}")),
+        None => None,
+    };
+    match dead_letters::fetch(job_id, query.limit.unwrap_or(100)).await {
+        Ok(dead_letters) => HttpResponse::Ok().json(dead_letters),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Error: {e}")),
+    }
+}
+
+#[derive(Deserialize)]
+struct DeadLetterQuery {
+    id: u64,
+}
+
+#[post("/dead_letters/replay")]
+pub async fn replay_dead_letter(
+    service_mgr: web::Data<ScannerServiceManager>,
+    auth: BasicAuth,
+    query: web::Query<DeadLetterQuery>,
+) -> impl Responder {
+    match service_mgr.replay_dead_letter(&auth, query.id).await {
+        Ok(()) => HttpResponse::Ok().body(format!("Replayed dead letter: {}", query.id)),
+        Err(e) => HttpResponse::InternalServerError().body(format!("Error: {e}")),
+    }
+}
@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use aws_sdk_sqs::{Client, types::DeleteMessageBatchRequestEntry};
+use tokio::time::sleep;
+
+use crate::utils::SQS_MAX_BATCH_SIZE;
+
+/// The maximum number of attempts to delete a message whose deletion failed on the SQS side.
+const MAX_DELETE_ATTEMPTS: u32 = 3;
+
+/// The delay before retrying failed deletions, multiplied by the number of attempts so far.
+const RETRY_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Deletes the given messages in batches, retrying the entries that failed due to transient errors.
+///
+/// # Returns
+///
+/// The receipt handles of the messages that couldn't be deleted.
+pub async fn delete_messages(
+    deleter: &(impl MessageDeleter + Sync),
+    sqs_url: &str,
+    receipt_handles: Vec<String>,
+) -> Vec<String> {
+    let mut pending = receipt_handles;
+    let mut undeleted = Vec::new();
+    for attempt in 1..=MAX_DELETE_ATTEMPTS {
+        let mut retryable = Vec::new();
+        for chunk in pending.chunks(SQS_MAX_BATCH_SIZE) {
+            match deleter.delete_batch(sqs_url, chunk).await {
+                Ok(failed) => {
+                    retryable.extend(failed.retryable);
+                    undeleted.extend(failed.permanent);
+                }
+                Err(e) => {
+                    log::warn!("Failed to delete a batch of SQS messages: {e:?}");
+                    retryable.extend_from_slice(chunk);
+                }
+            }
+        }
+        pending = retryable;
+        if pending.is_empty() {
+            break;
+        }
+        if attempt < MAX_DELETE_ATTEMPTS {
+            log::info!("Retrying deletion of {} SQS messages.", pending.len());
+            sleep(RETRY_BACKOFF * attempt).await;
+        }
+    }
+    undeleted.extend(pending);
+    undeleted
+}
+
+/// The receipt handles of the entries of a batch deletion that failed.
+#[derive(Default)]
+pub struct FailedDeletions {
+    /// Entries that failed due to SQS-side errors.
+    retryable: Vec<String>,
+    /// Entries that were rejected, e.g., because their receipt handles expired.
+    permanent: Vec<String>,
+}
+
+impl FailedDeletions {
+    /// Records a failed entry, which is retried unless the failure is the sender's fault.
+    fn push(&mut self, receipt_handle: String, sender_fault: bool) {
+        if sender_fault {
+            self.permanent.push(receipt_handle);
+        } else {
+            self.retryable.push(receipt_handle);
+        }
+    }
+}
+
+/// A queue whose messages can be deleted in batches.
+pub trait MessageDeleter {
+    /// Deletes a batch of at most [`SQS_MAX_BATCH_SIZE`] messages.
+    fn delete_batch(
+        &self,
+        sqs_url: &str,
+        receipt_handles: &[String],
+    ) -> impl Future<Output = Result<FailedDeletions>> + Send;
+}
+
+impl MessageDeleter for Client {
+    async fn delete_batch(
+        &self,
+        sqs_url: &str,
+        receipt_handles: &[String],
+    ) -> Result<FailedDeletions> {
+        let entries = receipt_handles
+            .iter()
+            .enumerate()
+            .map(|(idx, receipt_handle)| {
+                DeleteMessageBatchRequestEntry::builder()
+                    .id(idx.to_string())
+                    .receipt_handle(receipt_handle)
+                    .build()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let resp = self
+            .delete_message_batch()
+            .queue_url(sqs_url)
+            .set_entries(Some(entries))
+            .send()
+            .await?;
+
+        let mut failed = FailedDeletions::default();
+        for failure in resp.failed() {
+            log::warn!(
+                "Failed to delete SQS message: {}",
+                failure.message().unwrap_or_else(|| failure.code())
+            );
+            let Some(receipt_handle) = failure
+                .id()
+                .parse::<usize>()
+                .ok()
+                .and_then(|idx| receipt_handles.get(idx))
+            else {
+                continue;
+            };
+            failed.push(receipt_handle.clone(), failure.sender_fault());
+        }
+        Ok(failed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Mutex};
+
+    use anyhow::bail;
+
+    use super::*;
+
+    /// An in-memory queue failing the deletion of some messages.
+    #[derive(Default)]
+    struct FakeDeleter {
+        /// Messages whose deletion is rejected as the sender's fault.
+        rejected: Vec<String>,
+        /// The number of times the deletion of a message fails on the SQS side before succeeding.
+        num_failures: Mutex<HashMap<String, u32>>,
+        /// The number of times a whole batch request fails before succeeding.
+        num_request_failures: Mutex<u32>,
+        batch_sizes: Mutex<Vec<usize>>,
+        deleted: Mutex<Vec<String>>,
+    }
+
+    impl MessageDeleter for FakeDeleter {
+        async fn delete_batch(
+            &self,
+            _sqs_url: &str,
+            receipt_handles: &[String],
+        ) -> Result<FailedDeletions> {
+            self.batch_sizes.lock().unwrap().push(receipt_handles.len());
+            let mut num_request_failures = self.num_request_failures.lock().unwrap();
+            if *num_request_failures > 0 {
+                *num_request_failures -= 1;
+                bail!("Request failed.");
+            }
+            drop(num_request_failures);
+
+            let mut failed = FailedDeletions::default();
+            let mut num_failures = self.num_failures.lock().unwrap();
+            for receipt_handle in receipt_handles {
+                if self.rejected.contains(receipt_handle) {
+                    failed.push(receipt_handle.clone(), true);
+                    continue;
+                }
+                match num_failures.get_mut(receipt_handle) {
+                    Some(count) if *count > 0 => {
+                        *count -= 1;
+                        failed.push(receipt_handle.clone(), false);
+                    }
+                    _ => self.deleted.lock().unwrap().push(receipt_handle.clone()),
+                }
+            }
+            Ok(failed)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delete_messages() {
+        let receipt_handles: Vec<String> = (0..12).map(|idx| format!("receipt-{idx}")).collect();
+        let queue = FakeDeleter {
+            rejected: vec!["receipt-1".to_owned()],
+            num_failures: Mutex::new(HashMap::from([
+                ("receipt-2".to_owned(), 1),
+                ("receipt-3".to_owned(), MAX_DELETE_ATTEMPTS),
+            ])),
+            num_request_failures: Mutex::new(1),
+            ..Default::default()
+        };
+
+        let undeleted = delete_messages(&queue, "queue", receipt_handles.clone()).await;
+
+        // Rejected messages aren't retried, while messages failing on the SQS side are retried
+        // until the attempts run out.
+        assert_eq!(undeleted, vec!["receipt-1", "receipt-3"]);
+        let mut deleted = queue.deleted.into_inner().unwrap();
+        deleted.sort();
+        let mut expected: Vec<String> = receipt_handles
+            .into_iter()
+            .filter(|receipt_handle| !undeleted.contains(receipt_handle))
+            .collect();
+        expected.sort();
+        assert_eq!(deleted, expected);
+
+        // Messages are deleted in batches of at most 10, and the first batch request, which fails
+        // as a whole, is retried entirely.
+        assert_eq!(queue.batch_sizes.into_inner().unwrap(), vec![10, 2, 10, 2]);
+    }
+}
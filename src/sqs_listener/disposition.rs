@@ -0,0 +1,117 @@
+use std::collections::HashSet;
+
+use aws_sdk_sqs::types::{Message, MessageSystemAttributeName};
+
+/// What becomes of a received message once it has been handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageDisposition {
+    /// The message is done with and can be deleted right away.
+    Delete,
+    /// The message's objects have been handed to the listener, and the message is deleted once
+    /// they've all been durably handled.
+    InFlight,
+    /// The message is left in the queue.
+    Retain,
+}
+
+/// The messages of a received batch to act on once the batch has been handled.
+///
+/// Messages must be handled in order. Once a message of a FIFO message group is retained, the later
+/// messages of the group must not be handled before it, so they're released instead.
+#[derive(Debug, Default)]
+pub struct BatchDispositions {
+    /// Receipt handles of messages that are done with as soon as they've been received.
+    pub deletions: Vec<String>,
+    /// Receipt handles of messages queued behind a retained message of their FIFO message group,
+    /// which must be made visible again right away.
+    pub released: Vec<String>,
+    blocked_groups: HashSet<String>,
+}
+
+impl BatchDispositions {
+    /// Returns whether the given message must be handled, or has been released because a message
+    /// of its group was retained.
+    pub fn admit(&mut self, msg: &Message) -> bool {
+        let Some(group_id) = get_message_group_id(msg) else {
+            return true;
+        };
+        if !self.blocked_groups.contains(group_id) {
+            return true;
+        }
+        self.released
+            .extend(msg.receipt_handle().map(ToString::to_string));
+        false
+    }
+
+    /// Records how the given message has been handled.
+    pub fn record(&mut self, msg: &Message, disposition: MessageDisposition) {
+        match disposition {
+            MessageDisposition::Delete => self
+                .deletions
+                .extend(msg.receipt_handle().map(ToString::to_string)),
+            MessageDisposition::InFlight => {}
+            MessageDisposition::Retain => {
+                if let Some(group_id) = get_message_group_id(msg) {
+                    self.blocked_groups.insert(group_id.to_string());
+                }
+            }
+        }
+    }
+}
+
+pub fn get_message_group_id(msg: &Message) -> Option<&str> {
+    msg.attributes()
+        .and_then(|attributes| attributes.get(&MessageSystemAttributeName::MessageGroupId))
+        .map(String::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(receipt_handle: &str, group_id: Option<&str>) -> Message {
+        let builder = Message::builder()
+            .receipt_handle(receipt_handle)
+            .body(receipt_handle);
+        match group_id {
+            Some(group_id) => builder
+                .attributes(MessageSystemAttributeName::MessageGroupId, group_id)
+                .build(),
+            None => builder.build(),
+        }
+    }
+
+    #[test]
+    fn test_batch_dispositions() {
+        let messages = [
+            message("a-1", Some("a")),
+            message("b-1", Some("b")),
+            message("a-2", Some("a")),
+            message("b-2", Some("b")),
+            message("a-3", Some("a")),
+            message("c-1", None),
+            message("c-2", None),
+        ];
+        let mut dispositions = BatchDispositions::default();
+        let mut handled = Vec::new();
+        for msg in &messages {
+            if !dispositions.admit(msg) {
+                continue;
+            }
+            let body = msg.body().unwrap_or_default();
+            handled.push(body);
+            let disposition = match body {
+                "a-1" | "c-1" => MessageDisposition::Retain,
+                "b-1" => MessageDisposition::InFlight,
+                _ => MessageDisposition::Delete,
+            };
+            dispositions.record(msg, disposition);
+        }
+
+        // The messages of group `a` after the retained one are released without being handled,
+        // while messages outside of any group are never held back.
+        assert_eq!(handled, vec!["a-1", "b-1", "b-2", "c-1", "c-2"]);
+        assert_eq!(dispositions.deletions, vec!["b-2", "c-2"]);
+        assert_eq!(dispositions.released, vec!["a-2", "a-3"]);
+    }
+}
@@ -0,0 +1,170 @@
+use std::time::Duration;
+
+use dashmap::DashMap;
+use tokio::time::Instant;
+
+/// SQS messages whose objects have been sent for buffering but not all durably handled yet.
+///
+/// Messages are keyed by their receipt handle.
+#[derive(Default)]
+pub struct InFlightMessages {
+    messages: DashMap<String, InFlightMessage>,
+}
+
+struct InFlightMessage {
+    pending_objects: usize,
+    received_at: Instant,
+    visible_until: Instant,
+}
+
+impl InFlightMessages {
+    /// Registers a message before its `num_objects` objects are sent for buffering.
+    pub fn register(
+        &self,
+        receipt_handle: String,
+        num_objects: usize,
+        received_at: Instant,
+        visible_until: Instant,
+    ) {
+        self.messages.insert(
+            receipt_handle,
+            InFlightMessage {
+                pending_objects: num_objects,
+                received_at,
+                visible_until,
+            },
+        );
+    }
+
+    /// Acknowledges that one object of the given message has been durably handled.
+    ///
+    /// # Returns
+    ///
+    /// Whether all objects of the message have been handled, in which case the message is no
+    /// longer tracked and can be deleted.
+    pub fn acknowledge(&self, receipt_handle: &str) -> bool {
+        let Some(mut message) = self.messages.get_mut(receipt_handle) else {
+            return false;
+        };
+        message.pending_objects = message.pending_objects.saturating_sub(1);
+        if message.pending_objects > 0 {
+            return false;
+        }
+        drop(message);
+        self.messages.remove(receipt_handle).is_some()
+    }
+
+    /// Returns the receipt handles of all messages that become visible again before `deadline`.
+    pub fn get_expiring(&self, deadline: Instant) -> Vec<String> {
+        self.messages
+            .iter()
+            .filter(|message| message.visible_until < deadline)
+            .map(|message| message.key().clone())
+            .collect()
+    }
+
+    /// Stops tracking the messages whose visibility can't be extended until `deadline` because they
+    /// were received more than `max_visibility_timeout` before it.
+    ///
+    /// # Returns
+    ///
+    /// The receipt handles of the evicted messages.
+    pub fn evict_unextendable(
+        &self,
+        deadline: Instant,
+        max_visibility_timeout: Duration,
+    ) -> Vec<String> {
+        let mut evicted = Vec::new();
+        self.messages.retain(|receipt_handle, message| {
+            if message.received_at + max_visibility_timeout >= deadline {
+                return true;
+            }
+            evicted.push(receipt_handle.clone());
+            false
+        });
+        evicted
+    }
+
+    /// Stops tracking the given message, e.g., because its receipt handle is no longer valid.
+    ///
+    /// Its objects are still ingested, but the message is left to become visible again.
+    pub fn evict(&self, receipt_handle: &str) {
+        self.messages.remove(receipt_handle);
+    }
+
+    /// Records that the visibility of the given message has been extended.
+    pub fn set_visible_until(&self, receipt_handle: &str, visible_until: Instant) {
+        if let Some(mut message) = self.messages.get_mut(receipt_handle) {
+            message.visible_until = visible_until;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_acknowledge() {
+        let in_flight = InFlightMessages::default();
+        let now = Instant::now();
+        in_flight.register("receipt".into(), 2, now, now);
+        assert!(!in_flight.acknowledge("unknown"));
+        assert!(!in_flight.acknowledge("receipt"));
+        assert!(in_flight.acknowledge("receipt"));
+        assert!(!in_flight.acknowledge("receipt"));
+    }
+
+    #[test]
+    fn test_get_expiring() {
+        let now = Instant::now();
+        let in_flight = InFlightMessages::default();
+        in_flight.register("soon".into(), 1, now, now + Duration::from_secs(10));
+        in_flight.register("later".into(), 1, now, now + Duration::from_secs(100));
+        assert_eq!(
+            vec!["soon".to_string()],
+            in_flight.get_expiring(now + Duration::from_secs(50))
+        );
+
+        in_flight.set_visible_until("soon", now + Duration::from_secs(200));
+        assert!(
+            in_flight
+                .get_expiring(now + Duration::from_secs(50))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_evict() {
+        let now = Instant::now();
+        let in_flight = InFlightMessages::default();
+        in_flight.register("old".into(), 1, now, now + Duration::from_secs(10));
+        in_flight.register(
+            "recent".into(),
+            1,
+            now + Duration::from_secs(100),
+            now + Duration::from_secs(10),
+        );
+        in_flight.register(
+            "invalid".into(),
+            1,
+            now + Duration::from_secs(100),
+            now + Duration::from_secs(10),
+        );
+
+        assert_eq!(
+            vec!["old".to_string()],
+            in_flight.evict_unextendable(now + Duration::from_secs(150), Duration::from_secs(100))
+        );
+        in_flight.evict("invalid");
+        assert_eq!(
+            vec!["recent".to_string()],
+            in_flight.get_expiring(now + Duration::from_secs(50))
+        );
+        assert!(!in_flight.acknowledge("old"));
+        assert!(!in_flight.acknowledge("invalid"));
+        assert!(in_flight.acknowledge("recent"));
+    }
+}
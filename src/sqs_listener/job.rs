@@ -1,32 +1,86 @@
-use anyhow::Result;
-use aws_sdk_sqs::Client;
-use tokio::{sync::mpsc::Sender, task::JoinHandle};
+use std::{sync::Arc, time::Duration};
 
+use anyhow::{Result, anyhow};
+use aws_sdk_sqs::{
+    Client,
+    types::{Message, MessageSystemAttributeName},
+};
+use tokio::{
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    task::JoinHandle,
+    time::{Instant, sleep},
+};
+
+use super::{
+    deletion::delete_messages,
+    disposition::{BatchDispositions, MessageDisposition, get_message_group_id},
+    in_flight::InFlightMessages,
+    visibility::{
+        IN_FLIGHT_VISIBILITY_TIMEOUT,
+        change_visibility,
+        extend_in_flight_messages,
+        get_deferred_message_visibility_timeout,
+        get_irrelevant_message_visibility_timeout,
+    },
+};
 use crate::{
-    sqs_listener::JobParams,
-    utils::{S3Event, S3Object},
+    buffering::{BufferedObject, HandledObject, ObjectRemoval, ObjectRouter},
+    database::dead_letters,
+    metrics,
+    sqs_listener::{IrrelevantMessagePolicy, JobParams, is_fifo_queue},
+    utils::{KeyFilter, ObjectLimits, S3Event, S3Notification, S3Object},
 };
 
+/// The delay before retrying a failed receive request.
+const RECEIVE_RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The message group of messages sent to a FIFO queue that didn't come from one.
+pub const DEFAULT_MESSAGE_GROUP_ID: &str = "default";
+
 pub struct Job {
     id: uuid::Uuid,
-    handle: JoinHandle<()>,
+    handles: Vec<JoinHandle<()>>,
 }
 
 impl Job {
-    pub fn spawn(client: Client, params: JobParams, sender: Sender<S3Object>) -> Self {
-        let handle = tokio::spawn(async move {
-            if let Err(e) = listen_to_sqs_queue(client, params, sender).await {
-                log::error!("Job execution failed: {e:?}");
-            }
-        });
-        Self {
-            id: uuid::Uuid::new_v4(),
-            handle,
+    pub fn spawn(id: uuid::Uuid, client: Client, params: JobParams, router: ObjectRouter) -> Self {
+        let in_flight = Arc::new(InFlightMessages::default());
+        let (notifier, handled_objects) = mpsc::unbounded_channel();
+        let mut handles = vec![
+            tokio::spawn(delete_handled_messages(
+                client.clone(),
+                params.get_sqs_url().to_string(),
+                in_flight.clone(),
+                handled_objects,
+            )),
+            tokio::spawn(extend_in_flight_messages(
+                client.clone(),
+                params.get_sqs_url().to_string(),
+                in_flight.clone(),
+            )),
+        ];
+        // The receive loops share the tracking of in-flight messages, and feed the same listener.
+        for worker_id in 1..params.get_concurrency() {
+            handles.push(spawn_receive_loop(
+                worker_id,
+                id,
+                client.clone(),
+                params.clone(),
+                router.clone(),
+                notifier.clone(),
+                in_flight.clone(),
+            ));
         }
+        handles.push(spawn_receive_loop(
+            0, id, client, params, router, notifier, in_flight,
+        ));
+        Self { id, handles }
     }
 
     pub fn cancel(&self) {
-        self.handle.abort();
+        for handle in &self.handles {
+            handle.abort();
+        }
     }
 
     pub const fn get_id(&self) -> uuid::Uuid {
@@ -34,89 +88,487 @@ impl Job {
     }
 }
 
-async fn listen_to_sqs_queue(
+fn spawn_receive_loop(
+    worker_id: u32,
+    id: uuid::Uuid,
     client: Client,
     job: JobParams,
-    sender: Sender<S3Object>,
-) -> Result<()> {
-    loop {
-        // TODO: Add adaptive visibility timeout handling:
-        // If there are too many irrelevant messages, increase the visibility timeout to reduce
-        // duplicated handling for these messages.
-        let resp = client
-            .receive_message()
-            .queue_url(job.get_sqs_url())
-            .max_number_of_messages(10)
-            .wait_time_seconds(10)
-            .send()
-            .await?;
-
-        let Some(messages) = resp.messages else {
-            log::info!("No messages received from SQS queue.");
-            continue;
+    router: ObjectRouter,
+    notifier: UnboundedSender<HandledObject>,
+    in_flight: Arc<InFlightMessages>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        // The key filter has been validated on job creation.
+        let key_filter = match job.get_object_params().get_key_filter() {
+            Ok(key_filter) => key_filter,
+            Err(e) => {
+                log::error!("Invalid key filter in receive loop {worker_id}: {e:?}");
+                return;
+            }
+        };
+        let object_limits = job.get_object_params().get_object_limits();
+        let receive_loop = ReceiveLoop {
+            id,
+            client,
+            job,
+            key_filter,
+            object_limits,
+            router,
+            notifier,
+            in_flight,
         };
+        if let Err(e) = receive_loop.run().await {
+            log::error!("Job execution failed in receive loop {worker_id}: {e:?}");
+        }
+    })
+}
 
-        for msg in messages {
-            if msg.body.is_none() {
-                log::warn!("Received SQS message with empty body. Skipping.");
-                continue;
-            }
+/// Deletes each SQS message once all of its objects have been durably handled by the buffer.
+async fn delete_handled_messages(
+    client: Client,
+    sqs_url: String,
+    in_flight: Arc<InFlightMessages>,
+    mut handled_objects: UnboundedReceiver<HandledObject>,
+) {
+    while let Some(handled) = handled_objects.recv().await {
+        // Collect all pending notifications so that their messages are deleted in batches.
+        let mut handled_batch = vec![handled];
+        while let Ok(handled) = handled_objects.try_recv() {
+            handled_batch.push(handled);
+        }
+        let receipt_handles: Vec<String> = handled_batch
+            .into_iter()
+            .filter_map(|handled| handled.receipt_handle)
+            .filter(|receipt_handle| in_flight.acknowledge(receipt_handle.as_str()))
+            .collect();
+        if receipt_handles.is_empty() {
+            continue;
+        }
+        let undeleted = delete_messages(&client, sqs_url.as_str(), receipt_handles).await;
+        if !undeleted.is_empty() {
+            log::error!("Failed to delete {} handled SQS messages.", undeleted.len());
+        }
+    }
+}
 
-            let event: S3Event = match serde_json::from_str(msg.body().unwrap()) {
-                Ok(deserialized) => deserialized,
-                Err(e) => {
-                    log::error!(
-                        "Failed to deserialize SQS message body as a S3 Event: {e:?}. Skipping."
-                    );
-                    continue;
-                }
-            };
+/// The attempt ID of the receive requests of a loop on a FIFO queue.
+///
+/// A failed receive request is retried with the same attempt ID, so that SQS returns the same
+/// messages if it had actually served the failed request.
+struct ReceiveRequestAttemptId {
+    is_fifo: bool,
+    current: Option<String>,
+}
 
-            let mut object_found = false;
-            for record in event.records {
-                if !record.event_name.starts_with("ObjectCreated:") {
-                    continue;
-                }
+impl ReceiveRequestAttemptId {
+    const fn new(is_fifo: bool) -> Self {
+        Self {
+            is_fifo,
+            current: None,
+        }
+    }
 
-                let bucket_name = record.s3.bucket.name.as_str();
-                if job.get_bucket() != bucket_name {
-                    continue;
-                }
+    /// Returns the attempt ID of the next receive request, if the queue is a FIFO queue.
+    fn get(&mut self) -> Option<String> {
+        if self.is_fifo && self.current.is_none() {
+            self.current = Some(uuid::Uuid::new_v4().to_string());
+        }
+        self.current.clone()
+    }
 
-                let object_key = record.s3.object.key.as_str();
-                if object_key.ends_with('/') || !object_key.starts_with(job.get_key_prefix()) {
+    /// Records that the last receive request succeeded, so that the next one is a new attempt.
+    fn reset(&mut self) {
+        self.current = None;
+    }
+}
+
+/// A loop receiving messages from the job's queue and handing their objects to the listener.
+struct ReceiveLoop {
+    id: uuid::Uuid,
+    client: Client,
+    job: JobParams,
+    key_filter: KeyFilter,
+    object_limits: ObjectLimits,
+    router: ObjectRouter,
+    notifier: UnboundedSender<HandledObject>,
+    in_flight: Arc<InFlightMessages>,
+}
+
+impl ReceiveLoop {
+    async fn run(self) -> Result<()> {
+        let visibility_timeout = i32::try_from(IN_FLIGHT_VISIBILITY_TIMEOUT.as_secs())?;
+        let mut receive_request_attempt_id = ReceiveRequestAttemptId::new(self.job.is_fifo());
+        loop {
+            let received_at = Instant::now();
+            let resp = match self
+                .client
+                .receive_message()
+                .queue_url(self.job.get_sqs_url())
+                .max_number_of_messages(10)
+                .wait_time_seconds(10)
+                .visibility_timeout(visibility_timeout)
+                .message_system_attribute_names(MessageSystemAttributeName::ApproximateReceiveCount)
+                .message_system_attribute_names(MessageSystemAttributeName::MessageGroupId)
+                .set_receive_request_attempt_id(receive_request_attempt_id.get())
+                .send()
+                .await
+            {
+                Ok(resp) => resp,
+                Err(e) => {
+                    log::error!("Failed to receive messages from SQS queue: {e:?}. Retrying.");
+                    sleep(RECEIVE_RETRY_BACKOFF).await;
                     continue;
                 }
+            };
+            receive_request_attempt_id.reset();
 
-                let s3_object = S3Object::new(
-                    bucket_name.to_string(),
-                    object_key.to_string(),
-                    usize::try_from(record.s3.object.size)?,
-                );
+            let Some(messages) = resp.messages else {
+                log::info!("No messages received from SQS queue.");
+                continue;
+            };
+            self.handle_messages(messages, received_at).await?;
+        }
+    }
 
-                log::info!("Found S3 object from SQS message: {s3_object:?}");
-                sender.send(s3_object).await?;
-                object_found = true;
+    async fn handle_messages(&self, messages: Vec<Message>, received_at: Instant) -> Result<()> {
+        let mut dispositions = BatchDispositions::default();
+        for msg in &messages {
+            if dispositions.admit(msg) {
+                let disposition = self.handle_message(msg, received_at).await?;
+                dispositions.record(msg, disposition);
+            }
+        }
+        let BatchDispositions {
+            deletions,
+            released,
+            ..
+        } = dispositions;
+        if !deletions.is_empty() {
+            let undeleted = delete_messages(&self.client, self.job.get_sqs_url(), deletions).await;
+            if !undeleted.is_empty() {
+                log::error!("Failed to delete {} SQS messages.", undeleted.len());
             }
+        }
+        if !released.is_empty() {
+            log::info!(
+                "Releasing {} SQS messages queued behind a retained message of their message \
+                 group.",
+                released.len()
+            );
+            if let Err(e) = change_visibility(
+                &self.client,
+                self.job.get_sqs_url(),
+                &released,
+                Duration::ZERO,
+            )
+            .await
+            {
+                log::error!("Failed to release SQS messages: {e:?}");
+            }
+        }
+        Ok(())
+    }
 
-            if !object_found {
-                log::info!("No relevant S3 objects found in SQS message.");
-                continue;
+    async fn handle_message(
+        &self,
+        msg: &Message,
+        received_at: Instant,
+    ) -> Result<MessageDisposition> {
+        let notification = msg
+            .body()
+            .ok_or_else(|| anyhow!("The SQS message has an empty body."))
+            .and_then(S3Notification::from_sqs_message_body);
+        let event = match notification {
+            Ok(S3Notification::Event(event)) => event,
+            Ok(S3Notification::Test) => {
+                log::info!("Received S3 test event. Deleting it.");
+                return Ok(MessageDisposition::Delete);
             }
-            if let Some(receipt) = msg.receipt_handle() {
-                match client
-                    .delete_message()
-                    .queue_url(job.get_sqs_url())
-                    .receipt_handle(receipt)
-                    .send()
-                    .await
+            Err(e) => {
+                log::error!(
+                    "Failed to deserialize SQS message body as a S3 Event: {e:?}. Skipping."
+                );
+                if get_receive_count(msg) >= self.job.get_max_receive_count()
+                    && dead_letter_message(self.id, msg, &e).await
                 {
-                    Ok(_) => {}
-                    Err(e) => {
-                        log::error!("Failed to delete SQS message: {e:?}");
-                    }
+                    return Ok(MessageDisposition::Delete);
                 }
+                return Ok(MessageDisposition::Retain);
+            }
+        };
+
+        let (s3_objects, removals) = collect_relevant_objects(&self.job, event)?;
+        let has_relevant_objects = !s3_objects.is_empty();
+        let s3_objects = self.key_filter.retain(self.id, s3_objects);
+        if let Some(deferral) = s3_objects
+            .iter()
+            .filter_map(|s3_object| self.object_limits.get_deferral(s3_object))
+            .max()
+        {
+            // The whole message is received again once all of its objects are old enough.
+            defer_message(&self.client, self.job.get_sqs_url(), msg, deferral).await;
+            return Ok(MessageDisposition::Retain);
+        }
+        let s3_objects = self.object_limits.retain(self.id, s3_objects);
+        let has_removals = !removals.is_empty();
+        for removal in removals {
+            self.router.send_removal(removal).await?;
+        }
+
+        if s3_objects.is_empty() {
+            if has_removals || has_relevant_objects {
+                // Removals are handed to the buffer right away, and the job's objects may have
+                // been deliberately rejected by its filters, so the message is done either way.
+                return Ok(MessageDisposition::Delete);
+            }
+            log::info!("No relevant S3 objects found in SQS message.");
+            if handle_irrelevant_message(&self.client, &self.job, self.id, msg).await {
+                return Ok(MessageDisposition::Delete);
             }
+            return Ok(MessageDisposition::Retain);
+        }
+
+        // The message is deleted once all of its objects have been durably handled.
+        let receipt_handle = msg.receipt_handle().map(ToString::to_string);
+        if let Some(receipt_handle) = receipt_handle.as_ref() {
+            self.in_flight.register(
+                receipt_handle.clone(),
+                s3_objects.len(),
+                received_at,
+                received_at + IN_FLIGHT_VISIBILITY_TIMEOUT,
+            );
+        }
+        for s3_object in s3_objects {
+            self.router
+                .send(BufferedObject::new(
+                    s3_object,
+                    Some(self.notifier.clone()),
+                    receipt_handle.clone(),
+                ))
+                .await?;
+        }
+        Ok(MessageDisposition::InFlight)
+    }
+}
+
+/// Collects the objects relevant to the job from the records of an S3 event.
+///
+/// # Returns
+///
+/// A tuple of:
+///
+/// * The created objects.
+/// * The removed objects, if the job drops removed objects.
+fn collect_relevant_objects(
+    job: &JobParams,
+    event: S3Event,
+) -> Result<(Vec<S3Object>, Vec<ObjectRemoval>)> {
+    let mut s3_objects = Vec::new();
+    let mut removals = Vec::new();
+    for record in event.records {
+        if job.get_bucket() != record.s3.bucket.name {
+            continue;
+        }
+
+        let object_key = record.s3.object.key;
+        if object_key.ends_with('/') || !job.matches_key_prefix(object_key.as_str()) {
+            continue;
+        }
+
+        if record.event_name.starts_with("ObjectRemoved:") && job.get_drop_removed_objects() {
+            log::info!("Found removed S3 object from SQS message: {object_key}");
+            removals.push(ObjectRemoval {
+                bucket: record.s3.bucket.name,
+                key: object_key,
+                etag: record.s3.object.e_tag,
+                sequencer: record.s3.object.sequencer,
+            });
+            continue;
         }
+
+        if !record.event_name.starts_with("ObjectCreated:") {
+            continue;
+        }
+
+        let s3_object = S3Object::new(
+            record.s3.bucket.name,
+            object_key,
+            usize::try_from(record.s3.object.size)?,
+            record.s3.object.e_tag,
+        )
+        .with_last_modified(record.event_time)
+        .with_sequencer(record.s3.object.sequencer);
+
+        log::info!("Found S3 object from SQS message: {s3_object:?}");
+        s3_objects.push(s3_object);
+    }
+    Ok((s3_objects, removals))
+}
+
+/// Handles a message that didn't match the job according to the job's irrelevant message policy.
+///
+/// # Returns
+///
+/// Whether the message should be deleted from the job's queue.
+async fn handle_irrelevant_message(
+    client: &Client,
+    job: &JobParams,
+    job_id: uuid::Uuid,
+    msg: &Message,
+) -> bool {
+    let policy = job.get_irrelevant_message_policy();
+    let outcome = match policy {
+        IrrelevantMessagePolicy::Leave => {
+            back_off_irrelevant_message(client, job.get_sqs_url(), msg).await;
+            Ok("left")
+        }
+        IrrelevantMessagePolicy::Delete => Ok("deleted"),
+        IrrelevantMessagePolicy::Forward => forward_message(client, job, msg)
+            .await
+            .map(|()| "forwarded"),
+    };
+    let outcome = outcome.unwrap_or_else(|e| {
+        log::error!(
+            "Failed to apply the `{}` policy to an irrelevant SQS message: {e:?}",
+            policy.as_str()
+        );
+        "failed"
+    });
+    metrics::increment(
+        "sqs_irrelevant_messages_total",
+        &[
+            ("job_id", job_id.to_string().as_str()),
+            ("policy", policy.as_str()),
+            ("outcome", outcome),
+        ],
+    );
+    matches!(outcome, "deleted" | "forwarded")
+}
+
+/// Sends a copy of the message to the job's forward queue.
+async fn forward_message(client: &Client, job: &JobParams, msg: &Message) -> Result<()> {
+    let forward_sqs_url = job
+        .get_forward_sqs_url()
+        .ok_or_else(|| anyhow!("No forward SQS URL configured."))?;
+    let mut request = client
+        .send_message()
+        .queue_url(forward_sqs_url)
+        .message_body(msg.body().unwrap_or_default());
+    if is_fifo_queue(forward_sqs_url) {
+        request = request
+            .message_group_id(get_message_group_id(msg).unwrap_or(DEFAULT_MESSAGE_GROUP_ID))
+            .set_message_deduplication_id(msg.message_id().map(ToString::to_string));
+    }
+    request.send().await?;
+    Ok(())
+}
+
+/// Moves a message that can't be parsed to the dead-letter table, from which it can be inspected
+/// and replayed.
+///
+/// # Returns
+///
+/// Whether the message has been stored and should be deleted from the job's queue.
+async fn dead_letter_message(job_id: uuid::Uuid, msg: &Message, error: &anyhow::Error) -> bool {
+    let receive_count = get_receive_count(msg);
+    if let Err(e) = dead_letters::insert(
+        job_id,
+        msg.message_id(),
+        get_message_group_id(msg),
+        msg.body().unwrap_or_default(),
+        format!("{error:?}").as_str(),
+        receive_count,
+    )
+    .await
+    {
+        log::error!("Failed to store SQS message in the dead-letter table: {e:?}");
+        return false;
+    }
+    log::warn!(
+        "Moved SQS message {:?} to the dead-letter table after {receive_count} receives.",
+        msg.message_id()
+    );
+    metrics::increment(
+        "sqs_dead_letters_total",
+        &[("job_id", job_id.to_string().as_str())],
+    );
+    true
+}
+
+fn get_receive_count(msg: &Message) -> u32 {
+    msg.attributes()
+        .and_then(|attributes| attributes.get(&MessageSystemAttributeName::ApproximateReceiveCount))
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(1)
+}
+
+/// Lengthens the visibility timeout of a message that didn't match the job, based on how many times
+/// it has been received.
+async fn back_off_irrelevant_message(client: &Client, sqs_url: &str, msg: &Message) {
+    let Some(receipt_handle) = msg.receipt_handle() else {
+        return;
+    };
+    let receive_count = get_receive_count(msg);
+    let visibility_timeout = get_irrelevant_message_visibility_timeout(receive_count);
+    if let Err(e) = change_visibility(
+        client,
+        sqs_url,
+        &[receipt_handle.to_string()],
+        visibility_timeout,
+    )
+    .await
+    {
+        log::error!("Failed to back off irrelevant SQS message: {e:?}");
+        return;
+    }
+    log::info!(
+        "Irrelevant SQS message received {receive_count} times. Hiding it for {}s.",
+        visibility_timeout.as_secs()
+    );
+}
+
+/// Hides a message whose objects are too recent to be ingested until they're old enough.
+async fn defer_message(client: &Client, sqs_url: &str, msg: &Message, deferral: Duration) {
+    let Some(receipt_handle) = msg.receipt_handle() else {
+        return;
+    };
+    let visibility_timeout = get_deferred_message_visibility_timeout(deferral);
+    if let Err(e) = change_visibility(
+        client,
+        sqs_url,
+        &[receipt_handle.to_string()],
+        visibility_timeout,
+    )
+    .await
+    {
+        log::error!("Failed to defer SQS message: {e:?}");
+        return;
+    }
+    log::info!(
+        "SQS message has objects too recent to be ingested. Hiding it for {}s.",
+        visibility_timeout.as_secs()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_receive_request_attempt_id() {
+        let mut attempt_id = ReceiveRequestAttemptId::new(true);
+        let first = attempt_id.get();
+        assert!(first.is_some());
+        // A failed request is retried with the same attempt ID.
+        assert_eq!(first, attempt_id.get());
+        attempt_id.reset();
+        let second = attempt_id.get();
+        assert!(second.is_some());
+        assert_ne!(first, second);
+
+        let mut attempt_id = ReceiveRequestAttemptId::new(false);
+        assert_eq!(None, attempt_id.get());
+        attempt_id.reset();
+        assert_eq!(None, attempt_id.get());
     }
 }
@@ -1,13 +1,51 @@
-use serde::Deserialize;
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
 use url::Url;
 
-#[derive(Deserialize, Clone, Debug)]
+use crate::utils::{KeyPrefixParams, ObjectParams, deserialize_optional_param, deserialize_param};
+
+/// The default number of times a message that can't be parsed is received before it's moved to the
+/// dead-letter table.
+const DEFAULT_MAX_RECEIVE_COUNT: u32 = 5;
+
+/// The maximum number of receive loops a single job may run.
+const MAX_CONCURRENCY: u32 = 64;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct JobParams {
     region: String,
     bucket: String,
-    key_prefix: String,
+    #[serde(flatten)]
+    key_prefix_params: KeyPrefixParams,
     sqs_url: Url,
     dataset: Option<String>,
+    #[serde(flatten)]
+    object_params: ObjectParams,
+    #[serde(default)]
+    irrelevant_message_policy: IrrelevantMessagePolicy,
+    forward_sqs_url: Option<Url>,
+    /// Whether to drop objects that are removed from S3 before being flushed from the buffer.
+    #[serde(default, deserialize_with = "deserialize_param")]
+    drop_removed_objects: bool,
+    #[serde(default, deserialize_with = "deserialize_optional_param")]
+    max_receive_count: Option<u32>,
+    /// The number of receive loops polling the queue concurrently.
+    #[serde(default, deserialize_with = "deserialize_optional_param")]
+    concurrency: Option<u32>,
+}
+
+/// What to do with SQS messages that don't contain any object relevant to the job.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IrrelevantMessagePolicy {
+    /// Leave the message in the queue for other consumers, hiding it from this job for longer each
+    /// time it's received.
+    #[default]
+    Leave,
+    /// Delete the message from the queue.
+    Delete,
+    /// Send the message to `forward_sqs_url` and delete it from the queue.
+    Forward,
 }
 
 impl JobParams {
@@ -19,8 +57,20 @@ impl JobParams {
         &self.bucket
     }
 
+    pub fn get_key_prefixes(&self) -> Vec<&str> {
+        self.key_prefix_params.get_key_prefixes()
+    }
+
+    /// Returns whether the given key is under any of the job's key prefixes.
+    pub fn matches_key_prefix(&self, key: &str) -> bool {
+        self.get_key_prefixes()
+            .iter()
+            .any(|key_prefix| key.starts_with(key_prefix))
+    }
+
+    /// Returns the prefix shared by all of the job's key prefixes.
     pub fn get_key_prefix(&self) -> &str {
-        &self.key_prefix
+        self.key_prefix_params.get_key_prefix()
     }
 
     pub fn get_sqs_url(&self) -> &str {
@@ -30,4 +80,113 @@ impl JobParams {
     pub fn get_dataset(&self) -> Option<&str> {
         self.dataset.as_deref()
     }
+
+    pub const fn get_object_params(&self) -> &ObjectParams {
+        &self.object_params
+    }
+
+    pub const fn get_irrelevant_message_policy(&self) -> IrrelevantMessagePolicy {
+        self.irrelevant_message_policy
+    }
+
+    /// Returns whether the job's queue is a FIFO queue.
+    pub fn is_fifo(&self) -> bool {
+        is_fifo_queue(self.get_sqs_url())
+    }
+
+    pub fn get_forward_sqs_url(&self) -> Option<&str> {
+        self.forward_sqs_url.as_ref().map(Url::as_str)
+    }
+
+    pub const fn get_drop_removed_objects(&self) -> bool {
+        self.drop_removed_objects
+    }
+
+    /// Returns the number of times a message that can't be parsed is received before it's moved to
+    /// the dead-letter table.
+    pub fn get_max_receive_count(&self) -> u32 {
+        self.max_receive_count.unwrap_or(DEFAULT_MAX_RECEIVE_COUNT)
+    }
+
+    pub fn get_concurrency(&self) -> u32 {
+        self.concurrency.unwrap_or(1)
+    }
+
+    /// Validates that the parameters are consistent with each other.
+    pub fn validate(&self) -> Result<()> {
+        self.key_prefix_params.validate()?;
+        self.object_params.validate()?;
+        if self.irrelevant_message_policy == IrrelevantMessagePolicy::Forward
+            && self.forward_sqs_url.is_none()
+        {
+            bail!("`forward_sqs_url` is required by the `forward` irrelevant message policy.");
+        }
+        if self.max_receive_count == Some(0) {
+            bail!("`max_receive_count` must be positive.");
+        }
+        if self.is_fifo() && self.irrelevant_message_policy == IrrelevantMessagePolicy::Leave {
+            // A message left in a FIFO queue blocks all later messages of its message group.
+            bail!("FIFO queues require the `delete` or `forward` irrelevant message policy.");
+        }
+        if !(1..=MAX_CONCURRENCY).contains(&self.get_concurrency()) {
+            bail!("`concurrency` must be between 1 and {MAX_CONCURRENCY}.");
+        }
+        Ok(())
+    }
+}
+
+impl IrrelevantMessagePolicy {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Leave => "leave",
+            Self::Delete => "delete",
+            Self::Forward => "forward",
+        }
+    }
+}
+
+/// Returns whether the given SQS queue URL refers to a FIFO queue, whose name must end with the
+/// (case-sensitive) `.fifo` suffix.
+#[allow(clippy::case_sensitive_file_extension_comparisons)]
+pub fn is_fifo_queue(sqs_url: &str) -> bool {
+    sqs_url.ends_with(".fifo")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_params(sqs_url: &str, irrelevant_message_policy: Option<&str>) -> JobParams {
+        let mut params = serde_json::json!({
+            "region": "us-east-1",
+            "bucket": "bucket",
+            "key_prefix": "logs/",
+            "sqs_url": sqs_url,
+        });
+        if let Some(policy) = irrelevant_message_policy {
+            params["irrelevant_message_policy"] = policy.into();
+        }
+        serde_json::from_value(params).unwrap()
+    }
+
+    #[test]
+    fn test_fifo_queue() {
+        const FIFO_URL: &str = "https://sqs.us-east-1.amazonaws.com/123456789012/queue.fifo";
+        const STANDARD_URL: &str = "https://sqs.us-east-1.amazonaws.com/123456789012/queue";
+
+        assert!(is_fifo_queue(FIFO_URL));
+        assert!(!is_fifo_queue(STANDARD_URL));
+        assert!(!is_fifo_queue(
+            "https://sqs.us-east-1.amazonaws.com/123456789012/queue.FIFO"
+        ));
+        assert!(!is_fifo_queue(
+            "https://sqs.us-east-1.amazonaws.com/123456789012/queue-fifo"
+        ));
+
+        // A message left in a FIFO queue would block its message group.
+        assert!(parse_params(FIFO_URL, None).validate().is_err());
+        assert!(parse_params(FIFO_URL, Some("leave")).validate().is_err());
+        assert!(parse_params(FIFO_URL, Some("delete")).validate().is_ok());
+        assert!(parse_params(STANDARD_URL, None).validate().is_ok());
+    }
 }
@@ -1,5 +1,9 @@
+mod deletion;
+mod disposition;
+mod in_flight;
 mod job;
 mod job_params;
+mod visibility;
 
-pub use job::Job;
-pub use job_params::JobParams;
+pub use job::{DEFAULT_MESSAGE_GROUP_ID, Job};
+pub use job_params::{IrrelevantMessagePolicy, JobParams, is_fifo_queue};
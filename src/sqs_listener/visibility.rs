@@ -0,0 +1,182 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use aws_sdk_sqs::{Client, types::ChangeMessageVisibilityBatchRequestEntry};
+use tokio::time::{Instant, sleep};
+
+use super::in_flight::InFlightMessages;
+use crate::utils::SQS_MAX_BATCH_SIZE;
+
+/// The visibility timeout of received messages, renewed for as long as their objects are buffered.
+pub const IN_FLIGHT_VISIBILITY_TIMEOUT: Duration = Duration::from_mins(2);
+
+/// How often the visibility of in-flight messages is checked for renewal.
+const EXTENSION_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The maximum visibility timeout allowed by SQS.
+const MAX_VISIBILITY_TIMEOUT: Duration = Duration::from_hours(12);
+
+/// Computes the visibility timeout of a message that didn't match the job, doubling it every time
+/// the message is received so that repeatedly irrelevant messages are handled less and less often.
+pub fn get_irrelevant_message_visibility_timeout(receive_count: u32) -> Duration {
+    IN_FLIGHT_VISIBILITY_TIMEOUT
+        .saturating_mul(1 << receive_count.saturating_sub(1).min(16))
+        .min(MAX_VISIBILITY_TIMEOUT)
+}
+
+/// Computes the visibility timeout of a message whose objects are too recent to be ingested, so
+/// that it's received again once they're old enough.
+pub fn get_deferred_message_visibility_timeout(deferral: Duration) -> Duration {
+    // Visibility timeouts are in whole seconds, so round up.
+    Duration::from_secs(deferral.as_secs() + u64::from(deferral.subsec_nanos() > 0))
+        .max(Duration::from_secs(1))
+        .min(MAX_VISIBILITY_TIMEOUT)
+}
+
+/// Periodically extends the visibility of in-flight messages so that they don't become visible
+/// again while their objects are still buffered.
+pub async fn extend_in_flight_messages(
+    client: Client,
+    sqs_url: String,
+    in_flight: Arc<InFlightMessages>,
+) {
+    loop {
+        sleep(EXTENSION_INTERVAL).await;
+        let visible_until = Instant::now() + IN_FLIGHT_VISIBILITY_TIMEOUT;
+        let unextendable = in_flight.evict_unextendable(visible_until, MAX_VISIBILITY_TIMEOUT);
+        if !unextendable.is_empty() {
+            log::warn!(
+                "Stopped extending the visibility of {} in-flight messages received {} hours ago.",
+                unextendable.len(),
+                MAX_VISIBILITY_TIMEOUT.as_secs() / 3600
+            );
+        }
+
+        let receipt_handles = in_flight.get_expiring(Instant::now() + 2 * EXTENSION_INTERVAL);
+        if receipt_handles.is_empty() {
+            continue;
+        }
+        match change_visibility(
+            &client,
+            sqs_url.as_str(),
+            &receipt_handles,
+            IN_FLIGHT_VISIBILITY_TIMEOUT,
+        )
+        .await
+        {
+            Ok(extended) => {
+                log::info!(
+                    "Extended visibility of {} in-flight messages.",
+                    extended.len()
+                );
+                for receipt_handle in &extended {
+                    in_flight.set_visible_until(receipt_handle.as_str(), visible_until);
+                }
+                // The receipt handles of the other messages are no longer valid, e.g., because the
+                // messages have been received again.
+                for receipt_handle in &receipt_handles {
+                    if !extended.contains(receipt_handle) {
+                        in_flight.evict(receipt_handle.as_str());
+                    }
+                }
+            }
+            Err(e) => log::error!("Failed to extend visibility of in-flight messages: {e:?}"),
+        }
+    }
+}
+
+/// Changes the visibility timeout of the given messages.
+///
+/// # Returns
+///
+/// The receipt handles of the messages whose visibility was changed.
+pub async fn change_visibility(
+    client: &Client,
+    sqs_url: &str,
+    receipt_handles: &[String],
+    visibility_timeout: Duration,
+) -> Result<Vec<String>> {
+    let visibility_timeout = i32::try_from(visibility_timeout.as_secs())?;
+    let mut changed = Vec::with_capacity(receipt_handles.len());
+    for chunk in receipt_handles.chunks(SQS_MAX_BATCH_SIZE) {
+        let entries = chunk
+            .iter()
+            .enumerate()
+            .map(|(idx, receipt_handle)| {
+                ChangeMessageVisibilityBatchRequestEntry::builder()
+                    .id(idx.to_string())
+                    .receipt_handle(receipt_handle)
+                    .visibility_timeout(visibility_timeout)
+                    .build()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let resp = client
+            .change_message_visibility_batch()
+            .queue_url(sqs_url)
+            .set_entries(Some(entries))
+            .send()
+            .await?;
+        for failure in resp.failed() {
+            log::warn!(
+                "Failed to change visibility of SQS message: {}",
+                failure.message().unwrap_or_else(|| failure.code())
+            );
+        }
+        for success in resp.successful() {
+            if let Some(receipt_handle) = success
+                .id()
+                .parse::<usize>()
+                .ok()
+                .and_then(|idx| chunk.get(idx))
+            {
+                changed.push(receipt_handle.clone());
+            }
+        }
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_irrelevant_message_visibility_timeout() {
+        assert_eq!(
+            IN_FLIGHT_VISIBILITY_TIMEOUT,
+            get_irrelevant_message_visibility_timeout(0)
+        );
+        assert_eq!(
+            IN_FLIGHT_VISIBILITY_TIMEOUT,
+            get_irrelevant_message_visibility_timeout(1)
+        );
+        assert_eq!(
+            IN_FLIGHT_VISIBILITY_TIMEOUT * 4,
+            get_irrelevant_message_visibility_timeout(3)
+        );
+        assert_eq!(
+            MAX_VISIBILITY_TIMEOUT,
+            get_irrelevant_message_visibility_timeout(100)
+        );
+    }
+
+    #[test]
+    fn test_deferred_message_visibility_timeout() {
+        assert_eq!(
+            Duration::from_secs(5),
+            get_deferred_message_visibility_timeout(Duration::from_secs(5))
+        );
+        assert_eq!(
+            Duration::from_secs(6),
+            get_deferred_message_visibility_timeout(Duration::from_millis(5_001))
+        );
+        assert_eq!(
+            Duration::from_secs(1),
+            get_deferred_message_visibility_timeout(Duration::ZERO)
+        );
+        assert_eq!(
+            MAX_VISIBILITY_TIMEOUT,
+            get_deferred_message_visibility_timeout(Duration::from_hours(24))
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::{Context, Result, bail};
+use regex::{Captures, Regex};
+
+/// A template deriving the dataset of an object from its key.
+///
+/// The template is literal text with placeholders:
+///
+/// * `{segment[N]}`: The `N`-th (0-based) `/`-separated segment of the key.
+/// * `{N}` or `{name}`: The `N`-th or named capture group of a regex matched against the key.
+///
+/// Characters of the rendered dataset other than ASCII alphanumerics and `_` are replaced by `_`.
+#[derive(Clone, Debug)]
+pub struct DatasetTemplate {
+    parts: Vec<TemplatePart>,
+    regex: Option<Regex>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum TemplatePart {
+    Literal(String),
+    Segment(usize),
+    GroupIndex(usize),
+    GroupName(String),
+}
+
+impl DatasetTemplate {
+    /// # Errors
+    ///
+    /// Returns an error if:
+    ///
+    /// * The template or the regex is malformed.
+    /// * The template refers to capture groups that the regex doesn't have.
+    pub fn new(template: &str, regex: Option<&str>) -> Result<Self> {
+        let regex = regex
+            .map(|regex| Regex::new(regex).context("Invalid `dataset_regex` pattern."))
+            .transpose()?;
+        let parts = parse_template(template)?;
+        for part in &parts {
+            let has_group = match part {
+                TemplatePart::Literal(_) | TemplatePart::Segment(_) => continue,
+                TemplatePart::GroupIndex(idx) => regex
+                    .as_ref()
+                    .is_some_and(|regex| *idx < regex.captures_len()),
+                TemplatePart::GroupName(name) => regex.as_ref().is_some_and(|regex| {
+                    regex.capture_names().flatten().any(|other| other == name)
+                }),
+            };
+            if !has_group {
+                bail!("`dataset_template` refers to a capture group missing from `dataset_regex`.");
+            }
+        }
+        Ok(Self { parts, regex })
+    }
+
+    /// Renders the dataset of the object with the given key.
+    ///
+    /// # Returns
+    ///
+    /// The dataset, or `None` if the key doesn't have a segment or capture group the template
+    /// refers to.
+    pub fn render(&self, key: &str) -> Option<String> {
+        let captures = match &self.regex {
+            Some(regex) => Some(regex.captures(key)?),
+            None => None,
+        };
+        let mut dataset = String::new();
+        for part in &self.parts {
+            let value = match part {
+                TemplatePart::Literal(literal) => Some(literal.as_str()),
+                TemplatePart::Segment(idx) => key.split('/').nth(*idx),
+                TemplatePart::GroupIndex(idx) => get_group(captures.as_ref(), |c| c.get(*idx)),
+                TemplatePart::GroupName(name) => {
+                    get_group(captures.as_ref(), |c| c.name(name.as_str()))
+                }
+            };
+            dataset.push_str(value.filter(|value| !value.is_empty())?);
+        }
+        Some(
+            dataset
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() || c == '_' {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+
+fn get_group<'a>(
+    captures: Option<&Captures<'a>>,
+    get: impl FnOnce(&Captures<'a>) -> Option<regex::Match<'a>>,
+) -> Option<&'a str> {
+    captures.and_then(get).map(|group| group.as_str())
+}
+
+fn parse_template(template: &str) -> Result<Vec<TemplatePart>> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(TemplatePart::Literal(rest[..start].to_string()));
+        }
+        let Some(len) = rest[start..].find('}') else {
+            bail!("Unclosed placeholder in `dataset_template`.");
+        };
+        let placeholder = &rest[start + 1..start + len];
+        parts.push(parse_placeholder(placeholder).with_context(|| {
+            format!("Invalid placeholder `{{{placeholder}}}` in `dataset_template`.")
+        })?);
+        rest = &rest[start + len + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(TemplatePart::Literal(rest.to_string()));
+    }
+    if parts.is_empty() {
+        bail!("`dataset_template` must not be empty.");
+    }
+    Ok(parts)
+}
+
+fn parse_placeholder(placeholder: &str) -> Result<TemplatePart> {
+    if let Some(idx) = placeholder
+        .strip_prefix("segment[")
+        .and_then(|rest| rest.strip_suffix(']'))
+    {
+        return Ok(TemplatePart::Segment(idx.parse()?));
+    }
+    if let Ok(idx) = placeholder.parse() {
+        return Ok(TemplatePart::GroupIndex(idx));
+    }
+    if !placeholder.is_empty()
+        && placeholder
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Ok(TemplatePart::GroupName(placeholder.to_string()));
+    }
+    bail!("Unknown placeholder.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dataset_template() {
+        let template = DatasetTemplate::new("logs_{segment[1]}", None).unwrap();
+        assert_eq!(
+            template.render("logs/app-a/2025-01-01/1.log").as_deref(),
+            Some("logs_app_a")
+        );
+        assert_eq!(template.render("logs"), None);
+        assert_eq!(template.render("logs//1.log"), None);
+
+        let template =
+            DatasetTemplate::new("{service}_{1}", Some(r"^logs/(\d+)/(?<service>[^/]+)/")).unwrap();
+        assert_eq!(
+            template.render("logs/2025/app/1.log").as_deref(),
+            Some("app_2025")
+        );
+        assert_eq!(template.render("logs/app/1.log"), None);
+
+        assert!(DatasetTemplate::new("", None).is_err());
+        assert!(DatasetTemplate::new("{segment[1]", None).is_err());
+        assert!(DatasetTemplate::new("{segment[x]}", None).is_err());
+        assert!(DatasetTemplate::new("{1}", None).is_err());
+        assert!(DatasetTemplate::new("{2}", Some("(a)")).is_err());
+        assert!(DatasetTemplate::new("{name}", Some("(?<other>a)")).is_err());
+    }
+}
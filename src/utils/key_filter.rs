@@ -0,0 +1,126 @@
+use anyhow::{Context, Result};
+use globset::{Glob, GlobMatcher};
+use regex::Regex;
+
+use crate::{metrics, utils::S3Object};
+
+/// Include and exclude patterns on object keys, on top of a job's key prefix.
+///
+/// A key passes the filter if it matches every include pattern given and none of the exclude
+/// patterns. Glob patterns must match the whole key, with `*` also matching `/`, and support
+/// alternatives such as `*.{log,gz}`. Regex patterns match anywhere in the key unless anchored.
+#[derive(Clone, Debug, Default)]
+pub struct KeyFilter {
+    include_glob: Option<GlobMatcher>,
+    exclude_glob: Option<GlobMatcher>,
+    include_regex: Option<Regex>,
+    exclude_regex: Option<Regex>,
+}
+
+impl KeyFilter {
+    /// # Errors
+    ///
+    /// Returns an error if any of the patterns is invalid.
+    pub fn new(
+        include_glob: Option<&str>,
+        exclude_glob: Option<&str>,
+        include_regex: Option<&str>,
+        exclude_regex: Option<&str>,
+    ) -> Result<Self> {
+        Ok(Self {
+            include_glob: include_glob
+                .map(|pattern| compile_glob(pattern, "include_glob"))
+                .transpose()?,
+            exclude_glob: exclude_glob
+                .map(|pattern| compile_glob(pattern, "exclude_glob"))
+                .transpose()?,
+            include_regex: include_regex
+                .map(|pattern| compile_regex(pattern, "include_regex"))
+                .transpose()?,
+            exclude_regex: exclude_regex
+                .map(|pattern| compile_regex(pattern, "exclude_regex"))
+                .transpose()?,
+        })
+    }
+
+    pub fn matches(&self, key: &str) -> bool {
+        self.include_glob
+            .as_ref()
+            .is_none_or(|glob| glob.is_match(key))
+            && self
+                .include_regex
+                .as_ref()
+                .is_none_or(|regex| regex.is_match(key))
+            && !self
+                .exclude_glob
+                .as_ref()
+                .is_some_and(|glob| glob.is_match(key))
+            && !self
+                .exclude_regex
+                .as_ref()
+                .is_some_and(|regex| regex.is_match(key))
+    }
+
+    /// Keeps the objects whose keys pass the filter, counting the rejected ones for the given job
+    /// in the `rejected_objects_total` metric.
+    pub fn retain(&self, job_id: uuid::Uuid, objects: Vec<S3Object>) -> Vec<S3Object> {
+        let num_objects = objects.len();
+        let retained: Vec<S3Object> = objects
+            .into_iter()
+            .filter(|object| self.matches(object.get_key()))
+            .collect();
+        let num_rejected = num_objects - retained.len();
+        if num_rejected > 0 {
+            log::info!("[{job_id}] Rejected {num_rejected} objects by key filter.");
+            metrics::add(
+                "rejected_objects_total",
+                &[
+                    ("job_id", job_id.to_string().as_str()),
+                    ("reason", "key_filter"),
+                ],
+                num_rejected as u64,
+            );
+        }
+        retained
+    }
+}
+
+fn compile_glob(pattern: &str, name: &str) -> Result<GlobMatcher> {
+    Ok(Glob::new(pattern)
+        .with_context(|| format!("Invalid `{name}` pattern."))?
+        .compile_matcher())
+}
+
+fn compile_regex(pattern: &str, name: &str) -> Result<Regex> {
+    Regex::new(pattern).with_context(|| format!("Invalid `{name}` pattern."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_filter() {
+        assert!(KeyFilter::default().matches("logs/a.tmp"));
+
+        let filter = KeyFilter::new(
+            Some("logs/**/*.{log,gz}"),
+            Some("**/debug/**"),
+            None,
+            Some(r"\.tmp\.gz$"),
+        )
+        .unwrap();
+        assert!(filter.matches("logs/app/1.log"));
+        assert!(filter.matches("logs/app/2025/1.gz"));
+        assert!(!filter.matches("logs/app/_SUCCESS"));
+        assert!(!filter.matches("logs/app/debug/1.log"));
+        assert!(!filter.matches("logs/app/1.tmp.gz"));
+
+        let filter = KeyFilter::new(None, None, Some("^logs/[a-z]+/"), None).unwrap();
+        assert!(filter.matches("logs/app/1.log"));
+        assert!(!filter.matches("logs/App/1.log"));
+
+        assert!(KeyFilter::new(Some("logs/{a"), None, None, None).is_err());
+        assert!(KeyFilter::new(None, None, None, Some("(")).is_err());
+    }
+}
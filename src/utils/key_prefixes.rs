@@ -0,0 +1,154 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// The key prefixes of the objects a job ingests.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct KeyPrefixParams {
+    /// The key prefix of ingested objects, unless `key_prefixes` is given. An empty prefix selects
+    /// the whole bucket, and must be given explicitly.
+    key_prefix: Option<String>,
+    /// A JSON array of key prefixes of ingested objects, sharing the job's buffer.
+    #[serde(default, deserialize_with = "deserialize_key_prefixes")]
+    key_prefixes: Option<Vec<String>>,
+}
+
+impl KeyPrefixParams {
+    pub fn get_key_prefixes(&self) -> Vec<&str> {
+        self.key_prefixes
+            .as_deref()
+            .unwrap_or(self.key_prefix.as_slice())
+            .iter()
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Returns the prefix shared by all of the key prefixes.
+    pub fn get_key_prefix(&self) -> &str {
+        get_common_prefix(&self.get_key_prefixes())
+    }
+
+    /// Validates that exactly one of `key_prefix` and `key_prefixes` is given, and that the key
+    /// prefixes don't overlap.
+    pub fn validate(&self) -> Result<()> {
+        match (&self.key_prefix, &self.key_prefixes) {
+            (Some(_), Some(_)) => bail!("`key_prefix` and `key_prefixes` are mutually exclusive."),
+            (None, None) => bail!("One of `key_prefix` and `key_prefixes` is required."),
+            (None, Some(key_prefixes)) if key_prefixes.is_empty() => {
+                bail!("`key_prefixes` must not be empty.")
+            }
+            _ => validate_key_prefixes(&self.get_key_prefixes()),
+        }
+    }
+}
+
+/// Deserializes a list of key prefixes from either a sequence, or a string holding a JSON array as
+/// passed in query strings.
+fn deserialize_key_prefixes<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>, {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum KeyPrefixList {
+        List(Vec<String>),
+        Json(String),
+    }
+
+    match Option::<KeyPrefixList>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(KeyPrefixList::List(key_prefixes)) => Ok(Some(key_prefixes)),
+        Some(KeyPrefixList::Json(json)) => serde_json::from_str(&json)
+            .context("`key_prefixes` must be a JSON array of strings.")
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+/// Returns the longest prefix shared by all the given key prefixes.
+fn get_common_prefix<'a>(key_prefixes: &[&'a str]) -> &'a str {
+    let Some((first, others)) = key_prefixes.split_first() else {
+        return "";
+    };
+    let len = others.iter().fold(first.len(), |len, other| {
+        first[..len]
+            .char_indices()
+            .zip(other.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or_else(|| len.min(other.len()), |((idx, _), _)| idx)
+    });
+    &first[..len]
+}
+
+/// Validates that the given key prefixes are distinct and don't contain each other, so that no
+/// object is under more than one of them.
+fn validate_key_prefixes(key_prefixes: &[&str]) -> Result<()> {
+    for (idx, key_prefix) in key_prefixes.iter().enumerate() {
+        if let Some(other) = key_prefixes[idx + 1..]
+            .iter()
+            .find(|other| other.starts_with(key_prefix) || key_prefix.starts_with(*other))
+        {
+            bail!("Key prefixes `{key_prefix}` and `{other}` overlap.");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::web::Query;
+
+    use super::*;
+
+    #[test]
+    fn test_key_prefixes() {
+        let key_prefixes = ["logs/app-a/", "logs/app-b/", "logs/app-c/"];
+        assert_eq!(get_common_prefix(&key_prefixes), "logs/app-");
+        assert!(validate_key_prefixes(&key_prefixes).is_ok());
+
+        assert_eq!(get_common_prefix(&["logs/é1", "logs/é2"]), "logs/é");
+        assert_eq!(get_common_prefix(&["a/", "b/"]), "");
+        assert_eq!(get_common_prefix(&["logs/a/b", "logs/a"]), "logs/a");
+        assert_eq!(get_common_prefix(&["logs/"]), "logs/");
+
+        assert!(validate_key_prefixes(&["logs/a/", "logs/"]).is_err());
+        assert!(validate_key_prefixes(&["logs/a/", "logs/a/"]).is_err());
+    }
+
+    #[test]
+    fn test_key_prefix_params() {
+        // `["logs/a,b/","logs/c/"]`, URL-encoded.
+        let params = Query::<KeyPrefixParams>::from_query(
+            "key_prefixes=%5B%22logs%2Fa%2Cb%2F%22%2C%22logs%2Fc%2F%22%5D",
+        )
+        .unwrap()
+        .into_inner();
+        assert_eq!(params.get_key_prefixes(), ["logs/a,b/", "logs/c/"]);
+        assert_eq!(params.get_key_prefix(), "logs/");
+        assert!(params.validate().is_ok());
+
+        // Persisted parameters hold the prefixes as an array.
+        let params: KeyPrefixParams =
+            serde_json::from_str(&serde_json::to_string(&params).unwrap()).unwrap();
+        assert_eq!(params.get_key_prefixes(), ["logs/a,b/", "logs/c/"]);
+
+        let params = Query::<KeyPrefixParams>::from_query("key_prefix=logs%2F").unwrap();
+        assert_eq!(params.get_key_prefixes(), ["logs/"]);
+        assert!(params.validate().is_ok());
+
+        // The whole bucket is only selected by an explicitly empty prefix.
+        let params = Query::<KeyPrefixParams>::from_query("key_prefix=").unwrap();
+        assert_eq!(params.get_key_prefixes(), [""]);
+        assert!(params.validate().is_ok());
+        let params = Query::<KeyPrefixParams>::from_query("").unwrap();
+        assert!(params.validate().is_err());
+
+        let params = Query::<KeyPrefixParams>::from_query("key_prefixes=%5B%5D").unwrap();
+        assert!(params.validate().is_err());
+        assert!(Query::<KeyPrefixParams>::from_query("key_prefixes=logs%2F").is_err());
+        assert!(
+            Query::<KeyPrefixParams>::from_query("key_prefix=a%2F&key_prefixes=%5B%22b%2F%22%5D")
+                .unwrap()
+                .validate()
+                .is_err()
+        );
+    }
+}
@@ -1,9 +1,21 @@
+mod dataset_template;
+mod key_filter;
+mod key_prefixes;
+mod object_limits;
+mod object_params;
+mod query_param;
 mod s3_client;
 mod s3_object;
 mod sqs_client;
 mod sqs_s3_message;
 
+pub use dataset_template::DatasetTemplate;
+pub use key_filter::KeyFilter;
+pub use key_prefixes::KeyPrefixParams;
+pub use object_limits::{ObjectLimits, OversizedObjectPolicy};
+pub use object_params::ObjectParams;
+pub use query_param::{deserialize_optional_param, deserialize_param};
 pub use s3_client::create_s3_client;
 pub use s3_object::S3Object;
-pub use sqs_client::create_sqs_client;
-pub use sqs_s3_message::S3Event;
+pub use sqs_client::{SQS_MAX_BATCH_SIZE, create_sqs_client};
+pub use sqs_s3_message::{S3Event, S3Notification};
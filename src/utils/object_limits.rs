@@ -0,0 +1,230 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::{metrics, utils::S3Object};
+
+/// What to do with objects larger than a job's maximum object size.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OversizedObjectPolicy {
+    /// Leave the object out.
+    #[default]
+    Skip,
+    /// Ingest the object into the job's `oversized_dataset`.
+    Route,
+    /// Submit the object alone in its own compression job.
+    Isolate,
+}
+
+/// Constraints on the size and age of the objects a job ingests.
+///
+/// Objects whose last modification time is unknown pass the age constraints.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ObjectLimits {
+    pub min_size: Option<usize>,
+    pub max_size: Option<usize>,
+    pub min_age: Option<Duration>,
+    pub max_age: Option<Duration>,
+    pub oversized_policy: OversizedObjectPolicy,
+}
+
+impl ObjectLimits {
+    /// Validates that the limits are consistent with each other.
+    pub fn validate(&self) -> Result<()> {
+        if let (Some(min_size), Some(max_size)) = (self.min_size, self.max_size)
+            && min_size > max_size
+        {
+            bail!("`min_object_size` must not exceed `max_object_size`.");
+        }
+        if let (Some(min_age), Some(max_age)) = (self.min_age, self.max_age)
+            && min_age > max_age
+        {
+            bail!("`min_object_age_secs` must not exceed `max_object_age_secs`.");
+        }
+        if self.max_size.is_none() && self.oversized_policy != OversizedObjectPolicy::Skip {
+            bail!(
+                "The `{}` oversized object policy requires `max_object_size`.",
+                self.oversized_policy.as_str()
+            );
+        }
+        Ok(())
+    }
+
+    pub fn is_oversized(&self, object: &S3Object) -> bool {
+        self.max_size
+            .is_some_and(|max_size| object.get_size() > max_size)
+    }
+
+    /// Keeps the objects within the limits, along with the oversized objects unless they're
+    /// skipped. The rejected objects are counted for the given job in the `rejected_objects_total`
+    /// metric.
+    pub fn retain(&self, job_id: uuid::Uuid, objects: Vec<S3Object>) -> Vec<S3Object> {
+        let now = get_now();
+        let mut retained = Vec::with_capacity(objects.len());
+        for object in objects {
+            match self.get_rejection_reason(&object, now) {
+                None => retained.push(object),
+                Some(reason) => {
+                    log::info!("[{job_id}] Rejected object {object:?}: {reason}.");
+                    metrics::increment(
+                        "rejected_objects_total",
+                        &[("job_id", job_id.to_string().as_str()), ("reason", reason)],
+                    );
+                }
+            }
+        }
+        retained
+    }
+
+    /// Returns whether the object is within the limits, or oversized and not skipped.
+    pub fn accepts(&self, object: &S3Object) -> bool {
+        self.get_rejection_reason(object, get_now()).is_none()
+    }
+
+    /// Returns how long the object must be deferred until it's old enough to be ingested, if it's
+    /// too recent but otherwise accepted.
+    ///
+    /// Unlike the other limits, `min_age` is only a matter of time, so jobs that can come back to
+    /// an object defer it rather than reject it. Objects that the other limits reject are never
+    /// deferred, since they'd be rejected once old enough anyway.
+    pub fn get_deferral(&self, object: &S3Object) -> Option<Duration> {
+        self.get_deferral_at(object, get_now())
+    }
+
+    fn get_deferral_at(&self, object: &S3Object, now: u64) -> Option<Duration> {
+        let min_age = self.min_age?;
+        if self.get_permanent_rejection_reason(object, now).is_some() {
+            return None;
+        }
+        get_age(object, now)
+            .and_then(|age| min_age.checked_sub(age))
+            .filter(|deferral| !deferral.is_zero())
+    }
+
+    fn get_rejection_reason(&self, object: &S3Object, now: u64) -> Option<&'static str> {
+        self.get_permanent_rejection_reason(object, now)
+            .or_else(|| {
+                let age = get_age(object, now)?;
+                self.min_age
+                    .is_some_and(|min_age| age < min_age)
+                    .then_some("too_recent")
+            })
+    }
+
+    /// Returns why the object is rejected regardless of when it's ingested, if it is.
+    fn get_permanent_rejection_reason(&self, object: &S3Object, now: u64) -> Option<&'static str> {
+        if self
+            .min_size
+            .is_some_and(|min_size| object.get_size() < min_size)
+        {
+            return Some("too_small");
+        }
+        if self.is_oversized(object) && self.oversized_policy == OversizedObjectPolicy::Skip {
+            return Some("too_large");
+        }
+        let age = get_age(object, now)?;
+        if self.max_age.is_some_and(|max_age| age > max_age) {
+            return Some("too_old");
+        }
+        None
+    }
+}
+
+impl OversizedObjectPolicy {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Skip => "skip",
+            Self::Route => "route",
+            Self::Isolate => "isolate",
+        }
+    }
+}
+
+/// Returns the time since the object was last modified, if known.
+fn get_age(object: &S3Object, now: u64) -> Option<Duration> {
+    object
+        .get_last_modified()
+        .and_then(|last_modified| u64::try_from(last_modified).ok())
+        .map(|last_modified| Duration::from_secs(now.saturating_sub(last_modified)))
+}
+
+/// Returns the current time in seconds since the Unix epoch.
+fn get_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rejection_reason() {
+        let limits = ObjectLimits {
+            min_size: Some(1),
+            max_size: Some(100),
+            min_age: Some(Duration::from_secs(10)),
+            max_age: Some(Duration::from_hours(1)),
+            oversized_policy: OversizedObjectPolicy::Skip,
+        };
+        let now = 10_000;
+        let object = |size, last_modified| {
+            S3Object::new("bucket".to_string(), "key".to_string(), size, None)
+                .with_last_modified(last_modified)
+        };
+        assert_eq!(None, limits.get_rejection_reason(&object(1, None), now));
+        assert_eq!(
+            None,
+            limits.get_rejection_reason(&object(100, Some(9_990)), now)
+        );
+        assert_eq!(
+            Some("too_small"),
+            limits.get_rejection_reason(&object(0, None), now)
+        );
+        assert_eq!(
+            Some("too_large"),
+            limits.get_rejection_reason(&object(101, None), now)
+        );
+        assert_eq!(
+            Some("too_recent"),
+            limits.get_rejection_reason(&object(1, Some(9_995)), now)
+        );
+        assert_eq!(
+            Some("too_old"),
+            limits.get_rejection_reason(&object(1, Some(1_000)), now)
+        );
+
+        let limits = ObjectLimits {
+            oversized_policy: OversizedObjectPolicy::Isolate,
+            ..limits
+        };
+        assert!(limits.is_oversized(&object(101, None)));
+        assert_eq!(None, limits.get_rejection_reason(&object(101, None), now));
+
+        assert_eq!(
+            Some(Duration::from_secs(5)),
+            limits.get_deferral_at(&object(1, Some(9_995)), now)
+        );
+        assert_eq!(None, limits.get_deferral_at(&object(1, Some(9_990)), now));
+        assert_eq!(None, limits.get_deferral_at(&object(1, None), now));
+
+        // Objects that would be rejected once old enough are rejected right away.
+        let limits = ObjectLimits {
+            oversized_policy: OversizedObjectPolicy::Skip,
+            ..limits
+        };
+        assert_eq!(None, limits.get_deferral_at(&object(0, Some(9_995)), now));
+        assert_eq!(
+            Some("too_small"),
+            limits.get_rejection_reason(&object(0, Some(9_995)), now)
+        );
+        assert_eq!(None, limits.get_deferral_at(&object(101, Some(9_995)), now));
+        assert_eq!(
+            Some("too_large"),
+            limits.get_rejection_reason(&object(101, Some(9_995)), now)
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+use super::query_param::deserialize_optional_param;
+use crate::utils::{DatasetTemplate, KeyFilter, ObjectLimits, OversizedObjectPolicy};
+
+/// Parameters shared by every job type, selecting the objects to ingest and their dataset.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ObjectParams {
+    /// A template deriving the dataset of each object from its key, overriding `dataset` for the
+    /// objects it can be rendered for.
+    dataset_template: Option<String>,
+    /// The regex whose capture groups `dataset_template` may refer to.
+    dataset_regex: Option<String>,
+    /// Glob and regex patterns that keys must match to be ingested, on top of `key_prefix`.
+    include_glob: Option<String>,
+    include_regex: Option<String>,
+    /// Glob and regex patterns of keys to leave out.
+    exclude_glob: Option<String>,
+    exclude_regex: Option<String>,
+    /// Size constraints on ingested objects, in bytes.
+    #[serde(default, deserialize_with = "deserialize_optional_param")]
+    min_object_size: Option<usize>,
+    #[serde(default, deserialize_with = "deserialize_optional_param")]
+    max_object_size: Option<usize>,
+    /// Age constraints on ingested objects, in seconds since their last modification.
+    #[serde(default, deserialize_with = "deserialize_optional_param")]
+    min_object_age_secs: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_optional_param")]
+    max_object_age_secs: Option<u64>,
+    /// What to do with objects larger than `max_object_size`.
+    #[serde(default)]
+    oversized_object_policy: OversizedObjectPolicy,
+    /// The dataset oversized objects are ingested into under the `route` policy.
+    oversized_dataset: Option<String>,
+}
+
+impl ObjectParams {
+    pub fn get_object_limits(&self) -> ObjectLimits {
+        ObjectLimits {
+            min_size: self.min_object_size,
+            max_size: self.max_object_size,
+            min_age: self.min_object_age_secs.map(Duration::from_secs),
+            max_age: self.max_object_age_secs.map(Duration::from_secs),
+            oversized_policy: self.oversized_object_policy,
+        }
+    }
+
+    pub fn get_oversized_dataset(&self) -> Option<&str> {
+        self.oversized_dataset.as_deref()
+    }
+
+    pub fn get_dataset_template(&self) -> Result<Option<DatasetTemplate>> {
+        self.dataset_template
+            .as_deref()
+            .map(|template| DatasetTemplate::new(template, self.dataset_regex.as_deref()))
+            .transpose()
+    }
+
+    pub fn get_key_filter(&self) -> Result<KeyFilter> {
+        KeyFilter::new(
+            self.include_glob.as_deref(),
+            self.exclude_glob.as_deref(),
+            self.include_regex.as_deref(),
+            self.exclude_regex.as_deref(),
+        )
+    }
+
+    /// Validates that the parameters are consistent with each other.
+    pub fn validate(&self) -> Result<()> {
+        if self.dataset_regex.is_some() && self.dataset_template.is_none() {
+            bail!("`dataset_regex` requires `dataset_template`.");
+        }
+        self.get_dataset_template()?;
+        self.get_key_filter()?;
+        self.get_object_limits().validate()?;
+        if self.oversized_object_policy == OversizedObjectPolicy::Route
+            && self.oversized_dataset.is_none()
+        {
+            bail!("`oversized_dataset` is required by the `route` oversized object policy.");
+        }
+        Ok(())
+    }
+}
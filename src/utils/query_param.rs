@@ -0,0 +1,42 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Deserializer, de::Error};
+
+/// A parameter value given either as itself, or as a string such as a URL query parameter.
+///
+/// Query strings are only parsed into numbers and booleans when deserialized into the field that
+/// asks for one, which doesn't hold for structs with flattened fields. Such fields are parsed from
+/// strings here instead.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ParamValue<T> {
+    Value(T),
+    String(String),
+}
+
+impl<T: FromStr<Err: Display>> ParamValue<T> {
+    fn parse<E: Error>(self) -> Result<T, E> {
+        match self {
+            Self::Value(value) => Ok(value),
+            Self::String(value) => value.parse().map_err(E::custom),
+        }
+    }
+}
+
+/// Deserializes a parameter given either as itself or as a string.
+pub fn deserialize_param<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + FromStr<Err: Display>, {
+    ParamValue::deserialize(deserializer)?.parse()
+}
+
+/// Deserializes an optional parameter given either as itself or as a string.
+pub fn deserialize_optional_param<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + FromStr<Err: Display>, {
+    Option::<ParamValue<T>>::deserialize(deserializer)?
+        .map(ParamValue::parse)
+        .transpose()
+}
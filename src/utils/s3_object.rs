@@ -1,17 +1,45 @@
-#[derive(Debug, Clone)]
-#[allow(dead_code)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct S3Object {
     bucket: String,
     key: String,
     size: usize,
+    etag: Option<String>,
+    /// The last modification time, in seconds since the Unix epoch, if known.
+    #[serde(default)]
+    last_modified: Option<i64>,
+    /// The sequencer of the S3 event that reported the object's creation, if any.
+    #[serde(default)]
+    sequencer: Option<String>,
 }
 
 impl S3Object {
-    pub const fn new(bucket: String, key: String, size: usize) -> Self {
-        Self { bucket, key, size }
+    pub fn new(bucket: String, key: String, size: usize, etag: Option<String>) -> Self {
+        Self {
+            bucket,
+            key,
+            size,
+            // S3 returns ETags wrapped in double quotes, while event notifications don't.
+            etag: etag.map(|etag| etag.trim_matches('"').to_string()),
+            last_modified: None,
+            sequencer: None,
+        }
+    }
+
+    #[must_use]
+    pub const fn with_last_modified(mut self, last_modified: Option<i64>) -> Self {
+        self.last_modified = last_modified;
+        self
     }
 
-    pub fn _get_bucket(&self) -> &str {
+    #[must_use]
+    pub fn with_sequencer(mut self, sequencer: Option<String>) -> Self {
+        self.sequencer = sequencer;
+        self
+    }
+
+    pub fn get_bucket(&self) -> &str {
         &self.bucket
     }
 
@@ -22,4 +50,16 @@ impl S3Object {
     pub const fn get_size(&self) -> usize {
         self.size
     }
+
+    pub fn get_etag(&self) -> Option<&str> {
+        self.etag.as_deref()
+    }
+
+    pub const fn get_last_modified(&self) -> Option<i64> {
+        self.last_modified
+    }
+
+    pub fn get_sequencer(&self) -> Option<&str> {
+        self.sequencer.as_deref()
+    }
 }
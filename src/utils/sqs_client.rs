@@ -5,6 +5,9 @@ use aws_sdk_sqs::{
 };
 use secrecy::{ExposeSecret, SecretString};
 
+/// The maximum number of entries in a single SQS batch request.
+pub const SQS_MAX_BATCH_SIZE: usize = 10;
+
 pub async fn create_sqs_client(
     region_id: &str,
     access_key_id: &str,
@@ -1,4 +1,16 @@
-use serde::Deserialize;
+use anyhow::{Result, bail};
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Deserializer, de::Error as _};
+use serde_json::Value;
+
+/// A notification sent by S3 to an SQS queue.
+#[derive(Debug)]
+pub enum S3Notification {
+    Event(S3Event),
+    /// The test message S3 sends when a bucket notification is configured.
+    Test,
+}
 
 #[derive(Debug, Deserialize)]
 pub struct S3Event {
@@ -11,6 +23,13 @@ pub struct S3Record {
     pub s3: S3Entity,
     #[serde(rename = "eventName")]
     pub event_name: String,
+    /// The time of the event, in seconds since the Unix epoch.
+    #[serde(
+        rename = "eventTime",
+        default,
+        deserialize_with = "deserialize_event_time"
+    )]
+    pub event_time: Option<i64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -26,6 +45,244 @@ pub struct S3Bucket {
 
 #[derive(Debug, Deserialize)]
 pub struct S3Object {
+    // S3 event notifications URL-encode object keys.
+    #[serde(deserialize_with = "deserialize_url_encoded_key")]
     pub key: String,
+    // Removal events don't carry the object size.
+    #[serde(default)]
     pub size: u64,
+    #[serde(rename = "eTag")]
+    pub e_tag: Option<String>,
+    /// Orders the events of the same key: a later event has a greater sequencer, once the
+    /// sequencers are padded to the same length.
+    pub sequencer: Option<String>,
+}
+
+/// An SNS notification delivered to SQS without raw message delivery.
+#[derive(Deserialize)]
+struct SnsNotification {
+    #[serde(rename = "Message")]
+    message: String,
+}
+
+/// An S3 event delivered through `EventBridge`.
+#[derive(Deserialize)]
+struct EventBridgeEvent {
+    #[serde(rename = "detail-type")]
+    detail_type: String,
+    #[serde(default, deserialize_with = "deserialize_event_time")]
+    time: Option<i64>,
+    detail: EventBridgeDetail,
+}
+
+#[derive(Deserialize)]
+struct EventBridgeDetail {
+    bucket: S3Bucket,
+    object: EventBridgeObject,
+    reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct EventBridgeObject {
+    // Unlike S3 event notifications, `EventBridge` events carry object keys verbatim.
+    key: String,
+    #[serde(default)]
+    size: u64,
+    etag: Option<String>,
+    sequencer: Option<String>,
+}
+
+impl S3Notification {
+    /// Parses the body of an SQS message carrying an S3 notification.
+    ///
+    /// Besides raw S3 notifications, the body may be an SNS notification wrapping one, or an S3
+    /// event delivered through `EventBridge`, which is converted into a single-record event.
+    pub fn from_sqs_message_body(body: &str) -> Result<Self> {
+        let value: Value = serde_json::from_str(body)?;
+        if value.get("Records").is_some() {
+            return Ok(Self::Event(serde_json::from_value(value)?));
+        }
+        if value.get("Event").and_then(Value::as_str) == Some("s3:TestEvent") {
+            return Ok(Self::Test);
+        }
+        if value.get("Type").and_then(Value::as_str) == Some("Notification") {
+            let notification: SnsNotification = serde_json::from_value(value)?;
+            return Self::from_sqs_message_body(notification.message.as_str());
+        }
+        if value.get("source").and_then(Value::as_str) == Some("aws.s3") {
+            let event: EventBridgeEvent = serde_json::from_value(value)?;
+            return Ok(Self::Event(event.into()));
+        }
+        bail!("Unrecognized S3 notification format.")
+    }
+}
+
+impl From<EventBridgeEvent> for S3Event {
+    fn from(event: EventBridgeEvent) -> Self {
+        let reason = event.detail.reason.as_deref().unwrap_or("*");
+        // Map the detail type onto the event name of the equivalent S3 event notification.
+        let event_name = match event.detail_type.as_str() {
+            "Object Created" => format!("ObjectCreated:{reason}"),
+            "Object Deleted" => format!("ObjectRemoved:{reason}"),
+            detail_type => detail_type.replace(' ', ""),
+        };
+        Self {
+            records: vec![S3Record {
+                s3: S3Entity {
+                    bucket: event.detail.bucket,
+                    object: S3Object {
+                        key: event.detail.object.key,
+                        size: event.detail.object.size,
+                        e_tag: event.detail.object.etag,
+                        sequencer: event.detail.object.sequencer,
+                    },
+                },
+                event_name,
+                event_time: event.time,
+            }],
+        }
+    }
+}
+
+/// Decodes an object key from an S3 event notification, where spaces are encoded as `+` and other
+/// special characters are percent-encoded.
+fn decode_url_encoded_key(key: &str) -> Result<String> {
+    let key = key.replace('+', " ");
+    Ok(percent_decode_str(key.as_str()).decode_utf8()?.into_owned())
+}
+
+/// Deserializes an RFC 3339 event time, ignoring times that can't be parsed.
+fn deserialize_event_time<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<i64>, D::Error> {
+    let time: Option<String> = Option::deserialize(deserializer)?;
+    Ok(time
+        .and_then(|time| DateTime::from_str(time.as_str(), DateTimeFormat::DateTime).ok())
+        .map(|time| time.secs()))
+}
+
+fn deserialize_url_encoded_key<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<String, D::Error> {
+    let key = String::deserialize(deserializer)?;
+    decode_url_encoded_key(key.as_str()).map_err(D::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const S3_EVENT: &str = r#"{
+        "Records": [{
+            "eventName": "ObjectCreated:Put",
+            "eventTime": "1970-01-01T00:01:00.000Z",
+            "s3": {
+                "bucket": {"name": "bucket"},
+                "object": {
+                    "key": "logs/a.log",
+                    "size": 42,
+                    "eTag": "0a1b",
+                    "sequencer": "0055AED6DCD90281E5"
+                }
+            }
+        }]
+    }"#;
+
+    fn parse_event(body: &str) -> S3Event {
+        match S3Notification::from_sqs_message_body(body).unwrap() {
+            S3Notification::Event(event) => event,
+            S3Notification::Test => panic!("Unexpected test event."),
+        }
+    }
+
+    fn assert_single_record(event: &S3Event, event_name: &str) {
+        assert_eq!(event.records.len(), 1);
+        let record = &event.records[0];
+        assert_eq!(record.event_name, event_name);
+        assert_eq!(record.event_time, Some(60));
+        assert_eq!(record.s3.bucket.name, "bucket");
+        assert_eq!(record.s3.object.key, "logs/a.log");
+        assert_eq!(record.s3.object.size, 42);
+        assert_eq!(record.s3.object.e_tag.as_deref(), Some("0a1b"));
+        assert_eq!(
+            record.s3.object.sequencer.as_deref(),
+            Some("0055AED6DCD90281E5")
+        );
+    }
+
+    #[test]
+    fn test_parse_envelopes() {
+        let event = parse_event(S3_EVENT);
+        assert_single_record(&event, "ObjectCreated:Put");
+
+        let sns_notification = serde_json::json!({
+            "Type": "Notification",
+            "MessageId": "id",
+            "TopicArn": "arn:aws:sns:us-east-1:123456789012:topic",
+            "Message": S3_EVENT,
+        })
+        .to_string();
+        let event = parse_event(sns_notification.as_str());
+        assert_single_record(&event, "ObjectCreated:Put");
+
+        let eventbridge_event = r#"{
+            "version": "0",
+            "detail-type": "Object Created",
+            "source": "aws.s3",
+            "time": "1970-01-01T00:01:00Z",
+            "detail": {
+                "bucket": {"name": "bucket"},
+                "object": {
+                    "key": "logs/a.log",
+                    "size": 42,
+                    "etag": "0a1b",
+                    "sequencer": "0055AED6DCD90281E5"
+                },
+                "reason": "PutObject"
+            }
+        }"#;
+        let event = parse_event(eventbridge_event);
+        assert_single_record(&event, "ObjectCreated:PutObject");
+
+        let test_event = r#"{
+            "Service": "Amazon S3",
+            "Event": "s3:TestEvent",
+            "Time": "2025-01-01T00:00:00.000Z",
+            "Bucket": "bucket",
+            "RequestId": "id",
+            "HostId": "id"
+        }"#;
+        assert!(matches!(
+            S3Notification::from_sqs_message_body(test_event).unwrap(),
+            S3Notification::Test
+        ));
+
+        assert!(S3Notification::from_sqs_message_body(r#"{"foo": "bar"}"#).is_err());
+    }
+
+    #[test]
+    fn test_decode_url_encoded_key() {
+        let cases = [
+            ("logs/a.log", "logs/a.log"),
+            ("logs/my+file.log", "logs/my file.log"),
+            // A literal `+` is percent-encoded, so it must not become a space.
+            ("logs/a%2Bb.log", "logs/a+b.log"),
+            ("logs/a%2B+b.log", "logs/a+ b.log"),
+            ("logs/100%25.log", "logs/100%.log"),
+            ("logs/%3D%26%3F%23.log", "logs/=&?#.log"),
+            ("logs/%E6%97%A5%E5%BF%97.log", "logs/日志.log"),
+            ("logs/%F0%9F%93%9C.log", "logs/📜.log"),
+            // A stray `%` isn't an escape sequence and is kept as is.
+            ("logs/50%.log", "logs/50%.log"),
+        ];
+        for (encoded, expected) in cases {
+            assert_eq!(decode_url_encoded_key(encoded).unwrap(), expected);
+        }
+
+        // Invalid UTF-8.
+        assert!(decode_url_encoded_key("logs/%FF.log").is_err());
+
+        let event = parse_event(S3_EVENT.replace("a.log", "my+a%2B.log").as_str());
+        assert_eq!(event.records[0].s3.object.key, "logs/my a+.log");
+    }
 }